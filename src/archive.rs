@@ -0,0 +1,424 @@
+use std::collections::HashSet;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipEntryBuilder};
+use axum::body::Body;
+use futures::{AsyncWriteExt as _, StreamExt as _, TryStreamExt as _};
+use reqwest::Method;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncReadExt as _, DuplexStream, ReadBuf};
+use tokio::sync::oneshot;
+use tokio_tar::{Builder, Header};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::{error, info, warn};
+
+use crate::clients::{ClientResult, TiledClient};
+use crate::model::node::NodeAttributes;
+
+/// Size of the buffer between the task writing an archive and the response streaming it
+const ARCHIVE_BUFFER: usize = 64 * 1024;
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+impl ArchiveFormat {
+    pub fn parse(format: &str) -> Option<Self> {
+        match format {
+            "zip" => Some(Self::Zip),
+            "tar" => Some(Self::Tar),
+            _ => None,
+        }
+    }
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::Tar => "application/x-tar",
+        }
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::Tar => "tar",
+        }
+    }
+}
+
+/// Which parts of a run to include in an archive. An empty list includes everything.
+#[derive(Debug, Default)]
+pub struct ArchiveFilter {
+    pub streams: Vec<String>,
+    pub detectors: Vec<String>,
+}
+
+impl ArchiveFilter {
+    fn includes(list: &[String], name: &str) -> bool {
+        list.is_empty() || list.iter().any(|n| n == name)
+    }
+}
+
+/// A file from tiled to be included in an archive
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveFile {
+    /// Where the file is put in the archive
+    pub path: String,
+    pub stream: String,
+    pub detector: String,
+    /// The location of the file as recorded by tiled
+    pub data_uri: String,
+    #[serde(skip)]
+    id: u32,
+}
+
+/// A file that was found but could not be included in an archive
+#[derive(Debug, Clone, Serialize)]
+pub struct Skipped {
+    pub data_uri: String,
+    pub reason: String,
+}
+
+/// Written to the end of each archive to describe the run and what was included
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    run: &'a str,
+    metadata: Option<&'a Value>,
+    files: &'a [ArchiveFile],
+    skipped: &'a [Skipped],
+}
+
+/// The metadata and files of a run, ready to be written to an archive
+#[derive(Debug)]
+pub struct RunArchive {
+    run: String,
+    metadata: Option<Value>,
+    files: Vec<ArchiveFile>,
+    skipped: Vec<Skipped>,
+}
+
+impl RunArchive {
+    /// Find the files backing each array in the run's streams. This is done before the response
+    /// starts so that errors, eg an unknown run, can be reported with a suitable status.
+    pub async fn collect(
+        client: &TiledClient,
+        run: &str,
+        filter: &ArchiveFilter,
+        headers: Option<HeaderMap>,
+    ) -> ClientResult<Self> {
+        let node = client.metadata(run, headers.clone()).await?;
+        let metadata = match &*node.data.attributes {
+            NodeAttributes::Container(attrs) => attrs.metadata.raw().cloned(),
+            _ => None,
+        };
+        let streams = client.search(run, headers.clone(), &[]).await?;
+
+        let mut files = vec![];
+        let mut skipped = vec![];
+        let mut paths = HashSet::new();
+        for stream in streams
+            .data()
+            .filter(|s| matches!(*s.attributes, NodeAttributes::Container(_)))
+            .filter(|s| ArchiveFilter::includes(&filter.streams, &s.id))
+        {
+            let contents = client
+                .search(
+                    &format!("{run}/{}", stream.id),
+                    headers.clone(),
+                    &[("include_data_sources", "true".into())],
+                )
+                .await?;
+            for array in contents.into_data() {
+                let NodeAttributes::Array(attrs) = *array.attributes else {
+                    continue;
+                };
+                if !ArchiveFilter::includes(&filter.detectors, &array.id) {
+                    continue;
+                }
+                let assets = attrs.data_sources.into_iter().flatten();
+                for asset in assets.flat_map(|source| source.assets) {
+                    let id = match asset.id.map(u32::try_from) {
+                        Some(Ok(id)) if !asset.is_directory => id,
+                        _ => {
+                            skipped.push(Skipped {
+                                data_uri: asset.data_uri,
+                                reason: "Only individual files can be downloaded".into(),
+                            });
+                            continue;
+                        }
+                    };
+                    let name = file_name(&asset.data_uri).unwrap_or_else(|| id.to_string());
+                    let mut path = format!("{}/{}/{name}", stream.id, array.id);
+                    if !paths.insert(path.clone()) {
+                        // Files from different directories can share a name
+                        path = format!("{}/{}/{id}-{name}", stream.id, array.id);
+                        paths.insert(path.clone());
+                    }
+                    files.push(ArchiveFile {
+                        path,
+                        stream: stream.id.clone(),
+                        detector: array.id.clone(),
+                        data_uri: asset.data_uri,
+                        id,
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            run: run.into(),
+            metadata,
+            files,
+            skipped,
+        })
+    }
+
+    /// Stream the archive. Each file is downloaded from tiled as it is reached so nothing is
+    /// buffered on disk. If the archive can't be completed, the response body ends with an error
+    /// so the client doesn't mistake a truncated archive for a complete one.
+    pub fn stream(
+        self,
+        client: TiledClient,
+        format: ArchiveFormat,
+        headers: Option<HeaderMap>,
+    ) -> Body {
+        let (writer, reader) = tokio::io::duplex(ARCHIVE_BUFFER);
+        let (result_tx, result_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let run = self.run.clone();
+            let writer = match format {
+                ArchiveFormat::Zip => ArchiveWriter::Zip(ZipFileWriter::with_tokio(writer)),
+                ArchiveFormat::Tar => ArchiveWriter::Tar(Builder::new(writer)),
+            };
+            let result = self.write(&client, writer, headers).await;
+            if let Err(err) = &result {
+                error!("Failed to write archive of {run}: {err}");
+            }
+            // The archive task is done with the writer by now so the reader will see the end of
+            // the archive before this result.
+            let _ = result_tx.send(result);
+        });
+        let result = futures::stream::once(result_rx).filter_map(|result| async move {
+            match result {
+                Ok(Ok(())) => None,
+                Ok(Err(err)) => Some(Err(err)),
+                Err(_) => Some(Err(io::Error::other("Archive was not completed"))),
+            }
+        });
+        Body::from_stream(ReaderStream::new(reader).chain(result))
+    }
+
+    async fn write(
+        self,
+        client: &TiledClient,
+        mut writer: ArchiveWriter,
+        headers: Option<HeaderMap>,
+    ) -> io::Result<()> {
+        let mut files = vec![];
+        let mut skipped = self.skipped;
+        for file in self.files {
+            let response = client
+                .download(
+                    Method::GET,
+                    self.run.clone(),
+                    file.stream.clone(),
+                    file.detector.clone(),
+                    file.id,
+                    headers.clone(),
+                )
+                .await
+                .and_then(|resp| resp.error_for_status());
+            match response {
+                Ok(response) if let Some(reason) = writer.unsupported(&response) => {
+                    warn!(
+                        "Skipping {} in archive of {}: {reason}",
+                        file.path, self.run
+                    );
+                    skipped.push(Skipped {
+                        data_uri: file.data_uri,
+                        reason: reason.into(),
+                    });
+                }
+                Ok(response) => {
+                    info!("Adding {} to archive of {}", file.path, self.run);
+                    writer.add(&file.path, response).await?;
+                    files.push(file);
+                }
+                Err(err) => {
+                    warn!("Skipping {} in archive of {}: {err}", file.path, self.run);
+                    skipped.push(Skipped {
+                        data_uri: file.data_uri,
+                        reason: err.to_string(),
+                    });
+                }
+            }
+        }
+        let manifest = serde_json::to_vec_pretty(&Manifest {
+            run: &self.run,
+            metadata: self.metadata.as_ref(),
+            files: &files,
+            skipped: &skipped,
+        })?;
+        writer.add_bytes(MANIFEST, &manifest).await?;
+        writer.finish().await
+    }
+}
+
+/// The last segment of a file's URI
+fn file_name(data_uri: &str) -> Option<String> {
+    let url = url::Url::parse(data_uri).ok()?;
+    let name = url.path_segments()?.next_back()?;
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+enum ArchiveWriter {
+    Zip(ZipFileWriter<DuplexStream>),
+    Tar(Builder<DuplexStream>),
+}
+
+impl ArchiveWriter {
+    /// Why a file can't be added to this archive, if it can't
+    fn unsupported(&self, response: &reqwest::Response) -> Option<&'static str> {
+        match self {
+            // Buffering the file to find its size could use any amount of memory
+            ArchiveWriter::Tar(_) if response.content_length().is_none() => {
+                Some("Size of file is needed for tar archives but was not provided by tiled")
+            }
+            _ => None,
+        }
+    }
+
+    async fn add(&mut self, path: &str, response: reqwest::Response) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Zip(zip) => {
+                let entry = ZipEntryBuilder::new(path.to_owned().into(), Compression::Stored);
+                let mut entry = zip
+                    .write_entry_stream(entry)
+                    .await
+                    .map_err(io::Error::other)?;
+                let mut body = response.bytes_stream();
+                while let Some(chunk) = body.try_next().await.map_err(io::Error::other)? {
+                    entry.write_all(&chunk).await?;
+                }
+                entry.close().await.map_err(io::Error::other)
+            }
+            ArchiveWriter::Tar(tar) => {
+                let Some(size) = response.content_length() else {
+                    return Err(io::Error::other(format!("Size of {path} is not known")));
+                };
+                let body = response.bytes_stream().map_err(io::Error::other);
+                let data = Exact {
+                    inner: StreamReader::new(body).take(size),
+                    remaining: size,
+                };
+                tar.append_data(&mut tar_header(size), path, data).await
+            }
+        }
+    }
+
+    async fn add_bytes(&mut self, path: &str, data: &[u8]) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Zip(zip) => {
+                let entry = ZipEntryBuilder::new(path.to_owned().into(), Compression::Stored);
+                zip.write_entry_whole(entry, data)
+                    .await
+                    .map_err(io::Error::other)
+            }
+            ArchiveWriter::Tar(tar) => {
+                tar.append_data(&mut tar_header(data.len() as u64), path, data)
+                    .await
+            }
+        }
+    }
+
+    async fn finish(self) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Zip(zip) => zip.close().await.map(drop).map_err(io::Error::other),
+            ArchiveWriter::Tar(tar) => tar.into_inner().await.map(drop),
+        }
+    }
+}
+
+fn tar_header(size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs()),
+    );
+    header
+}
+
+/// Reader that fails if its inner reader ends before `remaining` bytes have been read. A tar
+/// entry shorter than the size in its header would corrupt the rest of the archive.
+struct Exact<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Exact<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        let read = (buf.filled().len() - before) as u64;
+        if read == 0 && this.remaining > 0 && buf.remaining() > 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("File ended {} bytes early", this.remaining),
+            )));
+        }
+        this.remaining = this.remaining.saturating_sub(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_zip::tokio::write::ZipFileWriter;
+    use tokio_tar::Builder;
+
+    use super::{ArchiveWriter, file_name};
+
+    fn response(body: reqwest::Body) -> reqwest::Response {
+        reqwest::Response::from(axum::http::Response::new(body))
+    }
+
+    #[tokio::test]
+    async fn tar_needs_file_size() {
+        let unsized_body = || {
+            let chunks = [Ok::<_, std::io::Error>("detector data")];
+            reqwest::Body::wrap_stream(futures::stream::iter(chunks))
+        };
+        let (writer, _) = tokio::io::duplex(64);
+        let tar = ArchiveWriter::Tar(Builder::new(writer));
+        assert!(tar.unsupported(&response(unsized_body())).is_some());
+        assert_eq!(tar.unsupported(&response("detector data".into())), None);
+
+        let (writer, _) = tokio::io::duplex(64);
+        let zip = ArchiveWriter::Zip(ZipFileWriter::with_tokio(writer));
+        assert_eq!(zip.unsupported(&response(unsized_body())), None);
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(
+            file_name("file://localhost/data/adsim-2-det.h5").as_deref(),
+            Some("adsim-2-det.h5")
+        );
+        assert_eq!(file_name("file://localhost/data/"), None);
+        assert_eq!(file_name("not a uri"), None);
+    }
+}
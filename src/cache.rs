@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use tracing::debug;
+
+/// Identifies a cached response. The caller's Authorization header is part of the key so that a
+/// response fetched with one user's credentials is never returned to another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    auth: Option<HeaderValue>,
+    path: String,
+    query: Vec<(String, String)>,
+}
+
+impl CacheKey {
+    pub fn new(path: &str, headers: Option<&HeaderMap>, query: &[(&str, Cow<'_, str>)]) -> Self {
+        Self {
+            auth: headers.and_then(|h| h.get(AUTHORIZATION)).cloned(),
+            path: path.to_owned(),
+            query: query
+                .iter()
+                .map(|(k, v)| ((*k).to_owned(), v.clone().into_owned()))
+                .collect(),
+        }
+    }
+}
+
+struct CacheEntry<T> {
+    value: T,
+    inserted: Instant,
+    /// Position in insertion order, used to find the oldest entry
+    sequence: u64,
+}
+
+/// Size-bounded cache of responses that are known not to change, eg those from runs that have
+/// finished. Entries expire after a fixed time to live and, once the cache is full, the oldest
+/// entries are evicted to make room for new ones.
+pub struct ResponseCache<T> {
+    entries: Mutex<HashMap<CacheKey, CacheEntry<T>>>,
+    capacity: usize,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    inserts: AtomicU64,
+}
+
+impl<T: Clone> ResponseCache<T> {
+    /// Create a cache holding up to `capacity` entries. A capacity of 0 disables caching.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            inserts: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<T> {
+        let mut entries = self.entries.lock().expect("Cache lock is not poisoned");
+        let value = match entries.get(key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        };
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let (hits, misses) = self.stats();
+        debug!(
+            hits,
+            misses,
+            "Cache {} for {}",
+            if value.is_some() { "hit" } else { "miss" },
+            key.path
+        );
+        value
+    }
+
+    pub fn insert(&self, key: CacheKey, value: T) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("Cache lock is not poisoned");
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.inserted.elapsed() < self.ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.sequence)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                value,
+                inserted: Instant::now(),
+                sequence: self.inserts.fetch_add(1, Ordering::Relaxed),
+            },
+        );
+    }
+
+    /// The number of lookups that have been served from and missed by the cache
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use reqwest::header::{AUTHORIZATION, HeaderMap};
+
+    use super::{CacheKey, ResponseCache};
+
+    fn key(path: &str) -> CacheKey {
+        CacheKey::new(path, None, &[])
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let cache = ResponseCache::new(4, Duration::from_secs(60));
+        assert_eq!(cache.get(&key("a")), None);
+        cache.insert(key("a"), 1);
+        assert_eq!(cache.get(&key("a")), Some(1));
+        assert_eq!(cache.stats(), (1, 1));
+    }
+
+    #[test]
+    fn scoped_by_auth() {
+        let cache = ResponseCache::new(4, Duration::from_secs(60));
+        let headers: HeaderMap = [(AUTHORIZATION, "user_a".parse().unwrap())]
+            .into_iter()
+            .collect();
+        cache.insert(CacheKey::new("a", Some(&headers), &[]), 1);
+        assert_eq!(cache.get(&key("a")), None);
+        assert_eq!(cache.get(&CacheKey::new("a", Some(&headers), &[])), Some(1));
+    }
+
+    #[test]
+    fn expired_entries() {
+        let cache = ResponseCache::new(4, Duration::ZERO);
+        cache.insert(key("a"), 1);
+        assert_eq!(cache.get(&key("a")), None);
+    }
+
+    #[test]
+    fn oldest_entry_evicted() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        cache.insert(key("a"), 1);
+        cache.insert(key("b"), 2);
+        cache.insert(key("c"), 3);
+        assert_eq!(cache.get(&key("a")), None);
+        assert_eq!(cache.get(&key("b")), Some(2));
+        assert_eq!(cache.get(&key("c")), Some(3));
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = ResponseCache::new(0, Duration::from_secs(60));
+        cache.insert(key("a"), 1);
+        assert_eq!(cache.get(&key("a")), None);
+    }
+}
@@ -1,21 +1,42 @@
 use std::borrow::Cow;
 use std::fmt;
+use std::hash::{BuildHasher, RandomState};
+use std::sync::Arc;
+use std::time::Duration;
 
+use async_graphql::ErrorExtensions;
 #[cfg(test)]
 use httpmock::MockServer;
 use reqwest::header::HeaderMap;
-use reqwest::{Client, Url};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
 use serde::de::DeserializeOwned;
-use tracing::{debug, info, instrument};
+use serde_json::Value;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::{debug, error, info, instrument, warn};
+use uuid::Uuid;
 
+use crate::cache::{CacheKey, ResponseCache};
 use crate::model::{app, node, table};
 
 pub type ClientResult<T> = Result<T, ClientError>;
 
+/// Default limit on the number of pages of search results to request from tiled
+pub const DEFAULT_MAX_PAGES: usize = 100;
+/// Default limit on the number of requests in flight to tiled for a single GraphQL query
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+/// Default limit on the number of elements returned when reading array values
+pub const DEFAULT_MAX_ARRAY_ELEMENTS: u64 = 1_000_000;
+
 #[derive(Clone)]
 pub struct TiledClient {
     client: Client,
     address: Url,
+    max_pages: usize,
+    max_concurrent_requests: usize,
+    max_array_elements: u64,
+    cache: Arc<ResponseCache<Arc<node::Root>>>,
+    metadata_cache: Arc<ResponseCache<Arc<node::Metadata>>>,
+    retry: RetryPolicy,
 }
 
 impl TiledClient {
@@ -28,8 +49,73 @@ impl TiledClient {
         Self {
             client: Client::new(),
             address,
+            max_pages: DEFAULT_MAX_PAGES,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+            cache: Arc::new(ResponseCache::new(0, Duration::ZERO)),
+            metadata_cache: Arc::new(ResponseCache::new(0, Duration::ZERO)),
+            retry: RetryPolicy::none(),
+        }
+    }
+    /// Limit the number of pages followed when collecting search results
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+    /// Limit the number of requests made concurrently on behalf of a single GraphQL query
+    pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: usize) -> Self {
+        self.max_concurrent_requests = max_concurrent_requests;
+        self
+    }
+    /// Refuse to read array values with more than `max_array_elements` elements
+    pub fn with_max_array_elements(mut self, max_array_elements: u64) -> Self {
+        self.max_array_elements = max_array_elements;
+        self
+    }
+    /// Cache up to `capacity` responses from finished runs for `ttl`
+    pub fn with_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.cache = Arc::new(ResponseCache::new(capacity, ttl));
+        self.metadata_cache = Arc::new(ResponseCache::new(capacity, ttl));
+        self
+    }
+    /// Give up on connecting to tiled after `connect` and on reading a response if no data is
+    /// received for `read`
+    pub fn with_timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        self.client = Client::builder()
+            .connect_timeout(connect)
+            .read_timeout(read)
+            .build()
+            .expect("Client configuration is valid");
+        self
+    }
+    /// Retry requests that fail because tiled is unavailable
+    pub fn with_retries(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+    pub fn max_array_elements(&self) -> u64 {
+        self.max_array_elements
+    }
+    /// Log the number of hits and misses of the response caches every `interval`, so that
+    /// their effectiveness can be monitored
+    pub async fn log_cache_stats(self, interval: Duration) {
+        let mut ticks = tokio::time::interval(interval);
+        // The first tick completes immediately, before anything could have been cached
+        ticks.tick().await;
+        loop {
+            ticks.tick().await;
+            let (search_hits, search_misses) = self.cache.stats();
+            let (metadata_hits, metadata_misses) = self.metadata_cache.stats();
+            info!(
+                search_hits,
+                search_misses, metadata_hits, metadata_misses, "Response cache statistics"
+            );
         }
     }
+    /// Create a new limit to be shared by everything resolving a single GraphQL query
+    pub fn request_limit(&self) -> RequestLimit {
+        RequestLimit(Arc::new(Semaphore::new(self.max_concurrent_requests)))
+    }
     #[instrument(skip(self))]
     async fn request<T: DeserializeOwned>(
         &self,
@@ -48,20 +134,115 @@ impl TiledClient {
         }
         info!("Querying: {request:?}");
 
-        let response = request.send().await?.error_for_status()?;
+        let response = self.send(request).await?.error_for_status()?;
         let body = response.text().await?;
-        serde_json::from_str(&body).map_err(|e| ClientError::InvalidResponse(e, body))
+        serde_json::from_str(&body).map_err(|e| ClientErrorKind::InvalidResponse(e, body).into())
     }
     pub async fn app_metadata(&self) -> ClientResult<app::AppMetadata> {
         self.request("/api/v1/", None, None).await
     }
+    /// Request the metadata of a single node. The metadata of runs that have finished is cached
+    /// in the same way as their search results.
+    pub async fn metadata(
+        &self,
+        path: &str,
+        headers: Option<HeaderMap>,
+    ) -> ClientResult<Arc<node::Metadata>> {
+        let key = CacheKey::new(path, headers.as_ref(), &[]);
+        if let Some(metadata) = self.metadata_cache.get(&key) {
+            return Ok(metadata);
+        }
+        let metadata: Arc<node::Metadata> = Arc::new(
+            self.request(&format!("api/v1/metadata/{}", path), headers, None)
+                .await?,
+        );
+        // Runs that are still in progress can gain a stop document at any time
+        if let node::NodeAttributes::Container(attrs) = &*metadata.data.attributes
+            && attrs.metadata.stop_doc().is_some()
+        {
+            self.metadata_cache.insert(key, metadata.clone());
+        }
+        Ok(metadata)
+    }
+    /// Request the order in which tiled returns the children of a node when no sort is given
+    pub async fn sorting(
+        &self,
+        path: &str,
+        headers: Option<HeaderMap>,
+    ) -> ClientResult<Vec<node::Sorting>> {
+        self.request::<node::SortingMetadata>(
+            &format!("api/v1/metadata/{}", path),
+            headers,
+            Some(&[("fields", "sorting".into())]),
+        )
+        .await
+        .map(node::SortingMetadata::into_sorting)
+    }
+    /// Search the given path, following tiled's pagination until every page has been collected
+    /// or the configured page limit is reached.
     pub async fn search(
         &self,
         path: &str,
         headers: Option<HeaderMap>,
         query: &[(&str, Cow<'_, str>)],
     ) -> ClientResult<node::Root> {
-        self.request(&format!("api/v1/search/{}", path), headers, Some(query))
+        let mut root: node::Root = self
+            .request(
+                &format!("api/v1/search/{}", path),
+                headers.clone(),
+                Some(query),
+            )
+            .await?;
+        let mut offset = root.page_len();
+        let mut pages = 1;
+        while root.has_next() && offset > 0 {
+            if pages >= self.max_pages {
+                warn!("Search of '{path}' truncated after {pages} pages ({offset} entries)");
+                break;
+            }
+            let page = self
+                .search_page(path, headers.clone(), query, offset, None)
+                .await?;
+            if page.page_len() == 0 {
+                break;
+            }
+            offset += page.page_len();
+            pages += 1;
+            root.extend(page);
+        }
+        Ok(root)
+    }
+    /// Search a path whose contents are known not to change, such as one within a run that has
+    /// finished. Responses are cached and shared between requests made with the same credentials.
+    pub async fn search_immutable(
+        &self,
+        path: &str,
+        headers: Option<HeaderMap>,
+        query: &[(&str, Cow<'_, str>)],
+    ) -> ClientResult<Arc<node::Root>> {
+        let key = CacheKey::new(path, headers.as_ref(), query);
+        if let Some(root) = self.cache.get(&key) {
+            return Ok(root);
+        }
+        let root = Arc::new(self.search(path, headers, query).await?);
+        self.cache.insert(key, root.clone());
+        Ok(root)
+    }
+    /// Request a single page of search results starting at the given offset
+    pub async fn search_page(
+        &self,
+        path: &str,
+        headers: Option<HeaderMap>,
+        query: &[(&str, Cow<'_, str>)],
+        offset: usize,
+        limit: Option<usize>,
+    ) -> ClientResult<node::Root> {
+        let mut query = query.to_vec();
+        query.push(("page[offset]", offset.to_string().into()));
+        if let Some(limit) = limit {
+            query.push(("page[limit]", limit.to_string().into()));
+        }
+        self.request(&format!("api/v1/search/{}", path), headers, Some(&query))
             .await
     }
     pub async fn table_full(
@@ -69,26 +250,76 @@ impl TiledClient {
         path: &str,
         columns: Option<Vec<String>>,
         headers: Option<HeaderMap>,
+    ) -> ClientResult<table::Table> {
+        self.table("full", path, None, columns, headers).await
+    }
+
+    /// Read a single partition of a table. Large tables are split into partitions by tiled; the
+    /// number available is given by the table's structure.
+    pub async fn table_partition(
+        &self,
+        path: &str,
+        partition: usize,
+        columns: Option<Vec<String>>,
+        headers: Option<HeaderMap>,
+    ) -> ClientResult<table::Table> {
+        self.table("partition", path, Some(partition), columns, headers)
+            .await
+    }
+
+    async fn table(
+        &self,
+        endpoint: &str,
+        path: &str,
+        partition: Option<usize>,
+        columns: Option<Vec<String>>,
+        headers: Option<HeaderMap>,
     ) -> ClientResult<table::Table> {
         let mut headers = headers.unwrap_or_default();
         headers.insert("accept", "application/json".parse().unwrap());
-        let query = columns.map(|columns| {
-            columns
-                .into_iter()
-                .map(|col| ("column", col.into()))
-                .collect::<Vec<_>>()
-        });
+        let query = partition
+            .map(|p| ("partition", p.to_string().into()))
+            .into_iter()
+            .chain(
+                columns
+                    .into_iter()
+                    .flatten()
+                    .map(|col| ("column", col.into())),
+            )
+            .collect::<Vec<_>>();
 
         self.request(
-            &format!("/api/v1/table/full/{}", path),
+            &format!("/api/v1/table/{endpoint}/{path}"),
             Some(headers),
-            query.as_deref(),
+            Some(&query),
         )
         .await
     }
 
+    /// Read the values of an array as nested JSON lists, optionally sliced using numpy syntax
+    pub async fn array_full(
+        &self,
+        path: &str,
+        slice: Option<&str>,
+        headers: Option<HeaderMap>,
+    ) -> ClientResult<Value> {
+        let mut headers = headers.unwrap_or_default();
+        headers.insert("accept", "application/json".parse().unwrap());
+        let query = slice.map(|slice| [("slice", slice.into())]);
+
+        self.request(
+            &format!("/api/v1/array/full/{}", path),
+            Some(headers),
+            query.as_ref().map(|q| q.as_slice()),
+        )
+        .await
+    }
+
+    /// Request a file from tiled. HEAD requests are passed on as they are so that a file's size
+    /// can be checked without transferring it.
     pub(crate) async fn download(
         &self,
+        method: Method,
         run: String,
         stream: String,
         det: String,
@@ -105,15 +336,119 @@ impl TiledClient {
             .push(&stream)
             .push(&det);
 
-        debug!("Downloading id={id} from {url}");
-        self.client
-            .get(url)
+        debug!("Downloading id={id} from {url} ({method})");
+        let request = self
+            .client
+            .request(method, url)
             .headers(headers.unwrap_or_default())
-            .query(&[("id", &id.to_string())])
-            .send()
+            .query(&[("id", &id.to_string())]);
+        self.send(request).await
+    }
+
+    /// Request the raw bytes of an array, optionally sliced, in a format supported by tiled. The
+    /// format can be a mimetype or a file extension; if not given, tiled picks one based on the
+    /// accept header.
+    pub(crate) async fn array_bytes(
+        &self,
+        path: &str,
+        slice: Option<&str>,
+        format: Option<&str>,
+        headers: Option<HeaderMap>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let query = [("slice", slice), ("format", format)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect::<Vec<_>>();
+        self.bytes(
+            "/api/v1/array/full",
+            path.split('/').filter(|s| !s.is_empty()),
+            &query,
+            headers,
+        )
+        .await
+    }
+
+    /// Request the contents of a table in a format supported by tiled, eg arrow, parquet or csv.
+    /// As for arrays, the format is chosen by tiled from the accept header if not given.
+    pub(crate) async fn table_bytes(
+        &self,
+        path: &[&str],
+        columns: &[String],
+        format: Option<&str>,
+        headers: Option<HeaderMap>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let query = columns
+            .iter()
+            .map(|col| ("column", col.as_str()))
+            .chain(format.map(|f| ("format", f)))
+            .collect::<Vec<_>>();
+        self.bytes("/api/v1/table/full", path.iter().copied(), &query, headers)
             .await
     }
 
+    async fn bytes<'p>(
+        &self,
+        endpoint: &str,
+        path: impl IntoIterator<Item = &'p str>,
+        query: &[(&str, &str)],
+        headers: Option<HeaderMap>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let mut url = self
+            .address
+            .join(endpoint)
+            .expect("Base address was cannot_be_a_base");
+        url.path_segments_mut()
+            .expect("Base address was cannot_be_a_base")
+            .extend(path);
+
+        debug!("Downloading from {url}");
+        let request = self
+            .client
+            .get(url)
+            .headers(headers.unwrap_or_default())
+            .query(query);
+        self.send(request).await
+    }
+
+    /// Send a request, retrying with increasing delays if tiled can't be reached or reports that
+    /// it is temporarily unavailable. Only used for GET and HEAD requests, which are idempotent
+    /// and so safe to repeat.
+    async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let Some(retry) = request
+                .try_clone()
+                .filter(|_| attempt < self.retry.max_retries)
+            else {
+                return request.send().await;
+            };
+            let result = retry.send().await;
+            let retryable = match &result {
+                Ok(response) => matches!(
+                    response.status(),
+                    StatusCode::BAD_GATEWAY
+                        | StatusCode::SERVICE_UNAVAILABLE
+                        | StatusCode::GATEWAY_TIMEOUT
+                ),
+                // A slow response is not retried as a retry would most likely be just as slow
+                Err(err) => err.is_connect(),
+            };
+            if !retryable {
+                return result;
+            }
+            let delay = self.retry.backoff(attempt);
+            warn!(
+                "Request to tiled failed ({}), retrying in {delay:?}",
+                match &result {
+                    Ok(response) => response.status().to_string(),
+                    Err(err) => err.to_string(),
+                }
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
     /// Create a new client for the given mock server
     #[cfg(test)]
     pub fn for_mock_server(server: &MockServer) -> Self {
@@ -121,45 +456,168 @@ impl TiledClient {
             // We're only in tests so panicking is fine
             address: server.base_url().parse().unwrap(),
             client: Client::new(),
+            max_pages: DEFAULT_MAX_PAGES,
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+            cache: Arc::new(ResponseCache::new(0, Duration::ZERO)),
+            metadata_cache: Arc::new(ResponseCache::new(0, Duration::ZERO)),
+            retry: RetryPolicy::none(),
+        }
+    }
+}
+
+/// How requests to tiled are retried when it is unavailable
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
         }
     }
+
+    /// The delay before the given retry. This grows exponentially up to the maximum backoff and
+    /// is jittered so that many clients retrying at once don't all hit tiled together.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        // A freshly seeded hasher gives a cheap random value without pulling in a dependency
+        let jitter = RandomState::new().hash_one(attempt) % 1000;
+        backoff / 2 + backoff / 2 * jitter as u32 / 1000
+    }
 }
 
+/// Limit on the number of requests to tiled that can be in flight at once. Clones share the same
+/// set of permits.
+#[derive(Clone)]
+pub struct RequestLimit(Arc<Semaphore>);
+
+impl RequestLimit {
+    /// Wait until a request can be made. The request should be made while the permit is held.
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.0
+            .acquire()
+            .await
+            .expect("Request limit semaphore is never closed")
+    }
+}
+
+/// An error from a request to tiled, logged when it is created along with an ID that lets
+/// clients refer to the full details.
+#[derive(Debug)]
+pub struct ClientError {
+    request_id: Uuid,
+    kind: ClientErrorKind,
+}
 #[derive(Debug)]
-pub enum ClientError {
+pub enum ClientErrorKind {
     InvalidPath(url::ParseError),
     ServerError(reqwest::Error),
     InvalidResponse(serde_json::Error, String),
 }
+impl From<ClientErrorKind> for ClientError {
+    fn from(kind: ClientErrorKind) -> ClientError {
+        let request_id = Uuid::new_v4();
+        error!(%request_id, "Error from tiled: {kind:?}");
+        ClientError { request_id, kind }
+    }
+}
 impl From<url::ParseError> for ClientError {
     fn from(err: url::ParseError) -> ClientError {
-        ClientError::InvalidPath(err)
+        ClientErrorKind::InvalidPath(err).into()
     }
 }
 impl From<reqwest::Error> for ClientError {
     fn from(err: reqwest::Error) -> ClientError {
-        ClientError::ServerError(err)
+        ClientErrorKind::ServerError(err).into()
+    }
+}
+
+/// Longest section of an invalid response body included in error messages
+const MAX_RESPONSE_SNIPPET: usize = 200;
+
+impl ClientError {
+    /// Stable identifier for the kind of error, for clients to react to
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            // Paths are built from node IDs so an invalid one can't refer to anything in tiled
+            ClientErrorKind::InvalidPath(_) => "NOT_FOUND",
+            ClientErrorKind::ServerError(err) => match err.status() {
+                Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => "TILED_FORBIDDEN",
+                Some(StatusCode::NOT_FOUND) => "NOT_FOUND",
+                Some(status) if status.is_client_error() => "TILED_ERROR",
+                _ => "TILED_UNAVAILABLE",
+            },
+            ClientErrorKind::InvalidResponse(..) => "INVALID_TILED_RESPONSE",
+        }
+    }
+    /// The HTTP status returned by tiled, if the request got as far as a response
+    pub fn status(&self) -> Option<StatusCode> {
+        match &self.kind {
+            ClientErrorKind::ServerError(err) => err.status(),
+            _ => None,
+        }
     }
 }
 
 impl std::fmt::Display for ClientError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            ClientError::InvalidPath(err) => write!(f, "Invalid URL path: {}", err),
-            ClientError::ServerError(err) => write!(f, "Tiled server error: {}", err),
-            ClientError::InvalidResponse(err, actual) => {
-                write!(f, "Invalid response: {err}, response: {actual}")
+        match &self.kind {
+            ClientErrorKind::InvalidPath(err) => write!(f, "Invalid URL path: {}", err),
+            ClientErrorKind::ServerError(err) => write!(f, "Tiled server error: {}", err),
+            ClientErrorKind::InvalidResponse(err, actual) => {
+                let end = actual.floor_char_boundary(MAX_RESPONSE_SNIPPET);
+                let ellipsis = if end < actual.len() { "..." } else { "" };
+                write!(
+                    f,
+                    "Invalid response: {err}, response: {}{ellipsis}",
+                    &actual[..end]
+                )
             }
         }
     }
 }
 
+impl ErrorExtensions for ClientError {
+    /// Convert into a GraphQL error with the error code, the status from tiled and the ID that
+    /// identifies the full error details in glazed's logs. Errors shared between fields by the
+    /// loader are extended once per field so this must not log anything itself.
+    fn extend(&self) -> async_graphql::Error {
+        let message = match &self.kind {
+            ClientErrorKind::InvalidResponse(err, _) => {
+                format!("Invalid response from tiled: {err}")
+            }
+            _ => self.to_string(),
+        };
+        async_graphql::Error::new(message).extend_with(|_, ext| {
+            ext.set("code", self.code());
+            if let Some(status) = self.status() {
+                ext.set("status", status.as_u16());
+            }
+            ext.set("requestId", self.request_id.to_string());
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use async_graphql::{ErrorExtensions, Value};
     use axum::http::HeaderMap;
     use httpmock::MockServer;
+    use serde_json::json;
 
-    use crate::clients::{ClientError, TiledClient};
+    use crate::clients::{ClientError, ClientErrorKind, RetryPolicy, TiledClient};
 
     #[tokio::test]
     async fn request() {
@@ -226,7 +684,11 @@ mod tests {
         let client = TiledClient::new("http://non-existent.example.com".parse().unwrap());
         let response = client.app_metadata().await;
 
-        let Err(ClientError::ServerError(err)) = response else {
+        let Err(ClientError {
+            kind: ClientErrorKind::ServerError(err),
+            ..
+        }) = response
+        else {
             panic!("Expected ServerError but got {response:?}");
         };
         assert!(
@@ -248,7 +710,11 @@ mod tests {
         let client = TiledClient::for_mock_server(&server);
         let response = client.app_metadata().await;
 
-        let Err(ClientError::ServerError(err)) = response else {
+        let Err(ClientError {
+            kind: ClientErrorKind::ServerError(err),
+            ..
+        }) = response
+        else {
             panic!("Expected ServerError but got {response:?}");
         };
 
@@ -274,11 +740,291 @@ mod tests {
         let client = TiledClient::for_mock_server(&server);
         let response = client.app_metadata().await;
 
-        let Err(ClientError::InvalidResponse(err, _)) = response else {
+        let Err(ClientError {
+            kind: ClientErrorKind::InvalidResponse(err, _),
+            ..
+        }) = response
+        else {
             panic!("Expected InvalidResponse but got {response:?}");
         };
 
         assert!(err.is_data());
         mock.assert();
     }
+
+    fn search_page(ids: &[&str], next: Option<&str>) -> serde_json::Value {
+        json!({
+            "data": ids.iter().map(|id| json!({"id": id})).collect::<Vec<_>>(),
+            "error": null,
+            "links": {"self": "", "next": next},
+            "meta": {"count": 3}
+        })
+    }
+
+    #[tokio::test]
+    async fn search_follows_pagination() {
+        let server = MockServer::start();
+        let first = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param_missing("page[offset]");
+                then.status(200)
+                    .json_body(search_page(&["a", "b"], Some("next")));
+            })
+            .await;
+        let second = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param("page[offset]", "2");
+                then.status(200).json_body(search_page(&["c"], None));
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server);
+        let root = client.search("", None, &[]).await.unwrap();
+
+        assert_eq!(root.page_len(), 3);
+        assert!(!root.has_next());
+        first.assert();
+        second.assert();
+    }
+
+    #[tokio::test]
+    async fn search_stops_at_max_pages() {
+        let server = MockServer::start();
+        let first = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param_missing("page[offset]");
+                then.status(200)
+                    .json_body(search_page(&["a", "b"], Some("next")));
+            })
+            .await;
+        let second = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param_exists("page[offset]");
+                then.status(200)
+                    .json_body(search_page(&["c"], Some("next")));
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server).with_max_pages(1);
+        let root = client.search("", None, &[]).await.unwrap();
+
+        assert_eq!(root.page_len(), 2);
+        first.assert();
+        second.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn search_immutable_is_cached() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/run");
+                then.status(200).json_body(search_page(&["a"], None));
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server).with_cache(10, Duration::from_secs(60));
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "user_a".parse().unwrap());
+
+        for _ in 0..2 {
+            let root = client.search_immutable("run", None, &[]).await.unwrap();
+            assert_eq!(root.page_len(), 1);
+        }
+        // A different user does not share the cached response
+        client
+            .search_immutable("run", Some(headers), &[])
+            .await
+            .unwrap();
+        mock.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn finished_run_metadata_is_cached() {
+        let server = MockServer::start();
+        let run = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/metadata/run");
+                then.status(200)
+                    .body_from_file("resources/metadata_run.json");
+            })
+            .await;
+        let stream = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/metadata/run/primary");
+                then.status(200)
+                    .body_from_file("resources/metadata_event_stream.json");
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server).with_cache(10, Duration::from_secs(60));
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "user_a".parse().unwrap());
+
+        for _ in 0..2 {
+            client.metadata("run", None).await.unwrap();
+            // Only the metadata of finished runs is cached
+            client.metadata("run/primary", None).await.unwrap();
+        }
+        client.metadata("run", Some(headers)).await.unwrap();
+        run.assert_calls(2);
+        stream.assert_calls(2);
+    }
+
+    fn retries(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn unavailable_tiled_is_retried() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(503);
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server).with_retries(retries(2));
+        let response = client.app_metadata().await;
+
+        let Err(ClientError {
+            kind: ClientErrorKind::ServerError(err),
+            ..
+        }) = response
+        else {
+            panic!("Expected ServerError but got {response:?}");
+        };
+        assert_eq!(err.status().map(|s| s.as_u16()), Some(503));
+        mock.assert_calls(3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(404);
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server).with_retries(retries(2));
+        let response = client.app_metadata().await;
+
+        assert!(response.is_err());
+        mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        let server = MockServer::start();
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(200)
+                    .delay(Duration::from_millis(500))
+                    .body_from_file("resources/metadata_app.json");
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server)
+            .with_timeouts(Duration::from_secs(1), Duration::from_millis(50));
+        let response = client.app_metadata().await;
+
+        let Err(ClientError {
+            kind: ClientErrorKind::ServerError(err),
+            ..
+        }) = response
+        else {
+            panic!("Expected ServerError but got {response:?}");
+        };
+        assert!(err.is_timeout(), "Expected timeout but got {err:?}");
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_not_retried() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(200)
+                    .delay(Duration::from_millis(500))
+                    .body_from_file("resources/metadata_app.json");
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server)
+            .with_timeouts(Duration::from_secs(1), Duration::from_millis(50))
+            .with_retries(retries(2));
+        let response = client.app_metadata().await;
+
+        assert!(response.is_err());
+        mock.assert_calls(1);
+    }
+
+    #[test]
+    fn backoff_is_bounded() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+        };
+        for attempt in 0..10 {
+            let delay = policy.backoff(attempt);
+            let expected = Duration::from_millis(100 * 2u64.pow(attempt)).min(policy.max_backoff);
+            assert!(delay >= expected / 2 && delay <= expected, "{delay:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn forbidden_error_extensions() {
+        let server = MockServer::start();
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(403);
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server);
+        let err = client.app_metadata().await.unwrap_err().extend();
+
+        let ext = err.extensions.unwrap();
+        assert_eq!(ext.get("code"), Some(&Value::from("TILED_FORBIDDEN")));
+        assert_eq!(ext.get("status"), Some(&Value::from(403)));
+        assert!(ext.get("requestId").is_some());
+    }
+
+    #[tokio::test]
+    async fn request_id_is_stable() {
+        let server = MockServer::start();
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/");
+                then.status(404);
+            })
+            .await;
+        let client = TiledClient::for_mock_server(&server);
+        let err = client.app_metadata().await.unwrap_err();
+
+        let first = err.extend().extensions.unwrap();
+        let second = err.extend().extensions.unwrap();
+        assert_eq!(first.get("requestId"), second.get("requestId"));
+    }
+
+    #[test]
+    fn invalid_response_body_not_exposed() {
+        let body = "#".repeat(1000);
+        let err = ClientError::from(ClientErrorKind::InvalidResponse(
+            serde_json::from_str::<Vec<u8>>("{}").unwrap_err(),
+            body.clone(),
+        ));
+        assert!(!err.extend().message.contains('#'));
+        assert!(err.to_string().len() < body.len());
+        assert_eq!(err.code(), "INVALID_TILED_RESPONSE");
+    }
 }
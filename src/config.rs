@@ -10,6 +10,9 @@ pub struct GlazedConfig {
     pub bind_address: SocketAddr,
     pub public_address: Option<Url>,
     pub tiled_client: TiledClientConfig,
+    /// If set, download links are signed so that they can be used without an Authorization
+    /// header
+    pub signed_links: Option<SignedLinksConfig>,
 }
 impl GlazedConfig {
     pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
@@ -23,7 +26,15 @@ impl GlazedConfig {
             public_address: None,
             tiled_client: TiledClientConfig {
                 address: Url::parse("http://localhost:8000").expect("Static URL is valid"),
+                max_pages: default_max_pages(),
+                max_concurrent_requests: default_max_concurrent_requests(),
+                max_array_elements: default_max_array_elements(),
+                cache: CacheConfig::default(),
+                connect_timeout_ms: default_connect_timeout_ms(),
+                read_timeout_ms: default_read_timeout_ms(),
+                retry: RetryConfig::default(),
             },
+            signed_links: None,
         }
     }
 }
@@ -31,4 +42,102 @@ impl GlazedConfig {
 #[derive(Deserialize, Debug, Clone)]
 pub struct TiledClientConfig {
     pub address: Url,
+    /// Upper limit on the number of pages followed for a single search
+    #[serde(default = "default_max_pages")]
+    pub max_pages: usize,
+    /// Upper limit on the number of requests to tiled in flight for a single GraphQL query
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    /// Upper limit on the number of elements returned when reading array values
+    #[serde(default = "default_max_array_elements")]
+    pub max_array_elements: u64,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Time allowed, in milliseconds, to establish a connection to tiled
+    #[serde(default = "default_connect_timeout_ms")]
+    pub connect_timeout_ms: u64,
+    /// Time allowed, in milliseconds, between reads of a response from tiled
+    #[serde(default = "default_read_timeout_ms")]
+    pub read_timeout_ms: u64,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+/// Settings for the cache of responses from runs that have finished
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Maximum number of responses to keep. Set to 0 to disable caching.
+    pub capacity: usize,
+    /// How long, in seconds, a response is kept before being requested again
+    pub ttl_seconds: u64,
+    /// How often, in seconds, the cache's hit and miss counts are logged. Set to 0 to disable.
+    pub stats_interval_seconds: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1000,
+            ttl_seconds: 3600,
+            stats_interval_seconds: 300,
+        }
+    }
+}
+
+/// Settings for retrying requests that fail because tiled is unavailable
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Number of times a failed request is retried. Set to 0 to disable retries.
+    pub max_retries: u32,
+    /// Delay, in milliseconds, before the first retry. This doubles for each subsequent retry.
+    pub initial_backoff_ms: u64,
+    /// Upper limit, in milliseconds, on the delay between retries
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 2000,
+        }
+    }
+}
+
+/// Settings for signing download links
+#[derive(Deserialize, Debug, Clone)]
+pub struct SignedLinksConfig {
+    /// Secret used to sign links and encrypt the credentials they carry. Changing it invalidates
+    /// every link already issued.
+    pub key: String,
+    /// How long, in seconds, a link can be used for after it is created
+    #[serde(default = "default_link_lifetime_seconds")]
+    pub lifetime_seconds: u64,
+}
+
+fn default_max_pages() -> usize {
+    crate::clients::DEFAULT_MAX_PAGES
+}
+
+fn default_max_concurrent_requests() -> usize {
+    crate::clients::DEFAULT_MAX_CONCURRENT_REQUESTS
+}
+
+fn default_max_array_elements() -> u64 {
+    crate::clients::DEFAULT_MAX_ARRAY_ELEMENTS
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_read_timeout_ms() -> u64 {
+    30000
+}
+
+fn default_link_lifetime_seconds() -> u64 {
+    900
 }
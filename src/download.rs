@@ -3,28 +3,58 @@ use axum::http::{HeaderMap, StatusCode};
 use serde_json::{Value, json};
 use tracing::error;
 
-const FORWARDED_HEADERS: [&str; 4] = [
+const FORWARDED_HEADERS: [&str; 7] = [
     "content-disposition",
     "content-type",
     "content-length",
     "last-modified",
+    "content-range",
+    "accept-ranges",
+    "etag",
 ];
 
+/// Request headers passed on to tiled so that downloads can be resumed or skipped if unchanged
+const CONDITIONAL_HEADERS: [&str; 4] = ["range", "if-range", "if-none-match", "if-modified-since"];
+
+/// Copy the range and conditional headers of a download request to the request made to tiled
+pub fn forward_conditional_headers(request: &HeaderMap, headers: &mut HeaderMap) {
+    for key in CONDITIONAL_HEADERS {
+        if let Some(value) = request.get(key) {
+            headers.insert(key, value.clone());
+        }
+    }
+}
+
+fn forwarded_headers(resp: &mut reqwest::Response) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for key in FORWARDED_HEADERS {
+        if let Some(value) = resp.headers_mut().remove(key) {
+            headers.insert(key, value);
+        }
+    }
+    headers
+}
+
 pub async fn forward_download_response(
     response: Result<reqwest::Response, reqwest::Error>,
 ) -> (StatusCode, HeaderMap, Body) {
     match response {
         Ok(mut resp) => match resp.status().as_u16() {
+                // Includes 206 for partial content when a range was requested
                 200..300  => {
-                    let mut headers = HeaderMap::new();
-                    for key in FORWARDED_HEADERS {
-                        if let Some(value) = resp.headers_mut().remove(key) {
-                            headers.insert(key, value);
-                        }
-                    }
+                    let status = resp.status();
+                    let headers = forwarded_headers(&mut resp);
                     let stream = Body::from_stream(resp.bytes_stream());
-                    (StatusCode::OK, headers, stream)
+                    (status, headers, stream)
                 },
+                // The client's copy is still current
+                304 => (StatusCode::NOT_MODIFIED, forwarded_headers(&mut resp), Body::empty()),
+                // The requested range is outside the file - content-range gives its size
+                416 => (
+                    StatusCode::RANGE_NOT_SATISFIABLE,
+                    forwarded_headers(&mut resp),
+                    Body::from_stream(resp.bytes_stream())
+                ),
                 400..500 => (
                     // Probably permission error or non-existent file - forward error to client
                     resp.status(),
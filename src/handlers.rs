@@ -3,22 +3,36 @@ use async_graphql::{EmptyMutation, EmptySubscription, Schema};
 use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
 use axum::Extension;
 use axum::body::Body;
-use axum::extract::{OptionalFromRequestParts, Path, State};
-use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::extract::{OptionalFromRequestParts, Path, Query, RawQuery, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
 use axum::response::{Html, IntoResponse};
-use reqwest::header::AUTHORIZATION;
+use reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE};
+use serde::Deserialize;
+use serde_json::json;
 use tracing::info;
 
+use crate::archive::{ArchiveFilter, ArchiveFormat, RunArchive};
 use crate::clients::TiledClient;
+use crate::links::LinkBase;
+use crate::loader::TiledLoader;
 use crate::model::TiledQuery;
+use crate::signing::{Signature, UrlSigner};
 
 pub async fn graphql_handler(
     auth_token: Option<AuthHeader>,
+    State(client): State<TiledClient>,
     schema: Extension<Schema<TiledQuery, EmptyMutation, EmptySubscription>>,
+    Extension(links): Extension<LinkBase>,
+    headers: HeaderMap,
     req: GraphQLRequest,
 ) -> GraphQLResponse {
     schema
-        .execute(req.into_inner().data(auth_token))
+        .execute(
+            req.into_inner()
+                .data(auth_token)
+                .data(links.resolve(&headers))
+                .data(TiledLoader::data_loader(client)),
+        )
         .await
         .into()
 }
@@ -31,29 +45,235 @@ pub async fn graphiql_handler(graphql_endpoint: Option<String>) -> impl IntoResp
     )
 }
 
+/// Query parameters of a signed download link
+#[derive(Debug, Deserialize)]
+pub struct DownloadParams {
+    expires: Option<u64>,
+    token: Option<String>,
+    signature: Option<String>,
+}
+
+/// Stream a file from tiled. Range and conditional requests are passed on so that downloads can
+/// be resumed, and HEAD requests return the headers without the file's contents.
 pub async fn download_handler(
     auth: Option<AuthHeader>,
     State(client): State<TiledClient>,
+    Extension(signer): Extension<Option<UrlSigner>>,
     Path((run, stream, det, id)): Path<(String, String, String, u32)>,
+    Query(params): Query<DownloadParams>,
+    method: Method,
+    request_headers: HeaderMap,
 ) -> (StatusCode, HeaderMap, Body) {
-    info!("Downloading {run}/{stream}/{det}/{id}");
-    let headers = auth.as_ref().map(AuthHeader::as_header_map);
-    let req = client.download(run, stream, det, id, headers).await;
+    info!("Downloading {run}/{stream}/{det}/{id} ({method})");
+    // A signed link carries the credential of the user it was created for
+    let auth = match (params.expires, params.signature) {
+        (Some(expires), Some(signature)) => {
+            let Some(signer) = signer else {
+                return (
+                    StatusCode::FORBIDDEN,
+                    HeaderMap::new(),
+                    "Signed download links are not enabled".into(),
+                );
+            };
+            let signature = Signature {
+                expires,
+                token: params.token,
+                signature,
+            };
+            match signer.verify(&asset_path(&run, &stream, &det, id.into()), &signature) {
+                Ok(signed_auth) => signed_auth.or(auth),
+                Err(err) => {
+                    return (
+                        StatusCode::FORBIDDEN,
+                        HeaderMap::new(),
+                        err.to_string().into(),
+                    );
+                }
+            }
+        }
+        _ => auth,
+    };
+    let mut headers = auth
+        .as_ref()
+        .map(AuthHeader::as_header_map)
+        .unwrap_or_default();
+    crate::download::forward_conditional_headers(&request_headers, &mut headers);
+    // axum answers HEAD requests with the GET handler, so only HEAD and GET reach here
+    let method = if method == Method::HEAD {
+        Method::HEAD
+    } else {
+        Method::GET
+    };
+    let req = client
+        .download(method, run, stream, det, id, Some(headers))
+        .await;
+    crate::download::forward_download_response(req).await
+}
+
+/// The path of an asset's download link, relative to glazed's public address
+pub fn asset_path(run: &str, stream: &str, det: &str, id: i64) -> String {
+    format!("asset/{run}/{stream}/{det}/{id}")
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArrayParams {
+    slice: Option<String>,
+    format: Option<String>,
+}
+
+/// Stream the bytes of an array from tiled. If no format is requested, the client's accept
+/// header is passed on so that tiled can choose one.
+pub async fn array_handler(
+    auth: Option<AuthHeader>,
+    State(client): State<TiledClient>,
+    Path(path): Path<String>,
+    Query(params): Query<ArrayParams>,
+    request_headers: HeaderMap,
+) -> (StatusCode, HeaderMap, Body) {
+    info!("Downloading array {path} ({params:?})");
+    let headers = forwarded_headers(auth, params.format.as_deref(), &request_headers);
+    let req = client
+        .array_bytes(
+            &path,
+            params.slice.as_deref(),
+            params.format.as_deref(),
+            Some(headers),
+        )
+        .await;
+    crate::download::forward_download_response(req).await
+}
+
+/// Stream a table from tiled, eg as arrow, parquet or csv. The format is chosen in the same way
+/// as for arrays. Columns can be selected by repeating the `column` query parameter.
+pub async fn table_handler(
+    auth: Option<AuthHeader>,
+    State(client): State<TiledClient>,
+    Path((run, stream, table)): Path<(String, String, String)>,
+    RawQuery(query): RawQuery,
+    request_headers: HeaderMap,
+) -> (StatusCode, HeaderMap, Body) {
+    let mut columns = vec![];
+    let mut format = None;
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match &*key {
+            "column" => columns.push(value.into_owned()),
+            "format" => format = Some(value.into_owned()),
+            _ => {}
+        }
+    }
+    info!("Downloading table {run}/{stream}/{table} (columns: {columns:?}, format: {format:?})");
+    let headers = forwarded_headers(auth, format.as_deref(), &request_headers);
+    let req = client
+        .table_bytes(
+            &[&run, &stream, &table],
+            &columns,
+            format.as_deref(),
+            Some(headers),
+        )
+        .await;
     crate::download::forward_download_response(req).await
 }
 
+/// Stream every file of a run, along with a manifest of its metadata, as a zip or tar archive.
+/// Streams and detectors can be selected by repeating the `stream` and `detector` query
+/// parameters.
+pub async fn archive_handler(
+    auth: Option<AuthHeader>,
+    State(client): State<TiledClient>,
+    Path(run): Path<String>,
+    RawQuery(query): RawQuery,
+) -> (StatusCode, HeaderMap, Body) {
+    let mut filter = ArchiveFilter::default();
+    let mut format = ArchiveFormat::Zip;
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match &*key {
+            "stream" => filter.streams.push(value.into_owned()),
+            "detector" => filter.detectors.push(value.into_owned()),
+            "format" => match ArchiveFormat::parse(&value) {
+                Some(fmt) => format = fmt,
+                None => {
+                    return (
+                        StatusCode::BAD_REQUEST,
+                        HeaderMap::new(),
+                        json!({"detail": format!("Unsupported archive format: {value}")})
+                            .to_string()
+                            .into(),
+                    );
+                }
+            },
+            _ => {}
+        }
+    }
+    info!("Archiving {run} ({filter:?}, format: {format:?})");
+    let headers = auth.as_ref().map(AuthHeader::as_header_map);
+    let archive = match RunArchive::collect(&client, &run, &filter, headers.clone()).await {
+        Ok(archive) => archive,
+        Err(err) => {
+            let status = match err.status() {
+                Some(status) if status.is_client_error() => status,
+                _ => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            return (
+                status,
+                HeaderMap::new(),
+                json!({"detail": err.to_string(), "code": err.code()})
+                    .to_string()
+                    .into(),
+            );
+        }
+    };
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    if let Ok(disposition) = HeaderValue::from_str(&format!(
+        "attachment; filename=\"{run}.{}\"",
+        format.extension()
+    )) {
+        response_headers.insert(CONTENT_DISPOSITION, disposition);
+    }
+    (
+        StatusCode::OK,
+        response_headers,
+        archive.stream(client, format, headers),
+    )
+}
+
+/// Headers to pass on to tiled when downloading data. The accept header is only needed if the
+/// format has not been given explicitly.
+fn forwarded_headers(
+    auth: Option<AuthHeader>,
+    format: Option<&str>,
+    request_headers: &HeaderMap,
+) -> HeaderMap {
+    let mut headers = auth
+        .as_ref()
+        .map(AuthHeader::as_header_map)
+        .unwrap_or_default();
+    if format.is_none()
+        && let Some(accept) = request_headers.get(ACCEPT)
+    {
+        headers.insert(ACCEPT, accept.clone());
+    }
+    headers
+}
+
 /// Extractor to accept an un-typed Authorization header (can be ApiKey/Bearer/Basic etc), and
 /// make it accessible as a HeaderValue to be forwarded rather than extracted into something to use
 /// locally (as the TypedHeader equivalent does).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AuthHeader(HeaderValue);
 
 impl AuthHeader {
     pub fn as_header_map(&self) -> HeaderMap {
         [(AUTHORIZATION, self.0.clone())].into_iter().collect()
     }
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
 }
 
-#[cfg(test)]
 impl From<HeaderValue> for AuthHeader {
     fn from(value: HeaderValue) -> Self {
         Self(value)
@@ -79,15 +299,24 @@ where
 
 #[cfg(test)]
 mod tests {
-    use axum::Router;
+    use std::time::Duration;
+
+    use async_zip::base::read1::seek::ZipArchiveReader;
     use axum::body::Body;
-    use axum::http::Request;
+    use axum::http::{HeaderValue, Request};
     use axum::response::IntoResponse;
     use axum::routing::get;
+    use axum::{Extension, Router};
+    use futures::StreamExt as _;
     use http_body_util::BodyExt as _;
+    use httpmock::MockServer;
+    use serde_json::{Value, json};
+    use tokio::io::AsyncReadExt as _;
     use tower::ServiceExt;
 
-    use super::AuthHeader;
+    use super::{AuthHeader, archive_handler, array_handler, download_handler, table_handler};
+    use crate::clients::TiledClient;
+    use crate::signing::UrlSigner;
 
     async fn auth_echo(auth: Option<AuthHeader>) -> impl IntoResponse {
         match auth {
@@ -128,4 +357,486 @@ mod tests {
             "No auth"
         );
     }
+
+    #[tokio::test]
+    async fn array_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/array/full/run/primary/det")
+                    .query_param("slice", "0,::2")
+                    .query_param("format", "application/octet-stream")
+                    .header("authorization", "auth_value");
+                then.status(200)
+                    .header("content-type", "application/octet-stream")
+                    .body([1, 2, 3, 4]);
+            })
+            .await;
+        let app = Router::new()
+            .route("/array/{*path}", get(array_handler))
+            .with_state(TiledClient::for_mock_server(&server));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/array/run/primary/det?slice=0,::2&format=application/octet-stream")
+                    .header("Authorization", "auth_value")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/octet-stream"
+        );
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            &[1, 2, 3, 4][..]
+        );
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn array_download_forwards_accept() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/array/full/run/primary/det")
+                    .query_param_missing("format")
+                    .header("accept", "application/x-hdf5");
+                then.status(200).body("hdf5");
+            })
+            .await;
+        let app = Router::new()
+            .route("/array/{*path}", get(array_handler))
+            .with_state(TiledClient::for_mock_server(&server));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/array/run/primary/det")
+                    .header("Accept", "application/x-hdf5")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn table_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/table/full/run/primary/internal")
+                    .query_param("column", "time")
+                    .query_param("column", "stage-x")
+                    .query_param("format", "parquet")
+                    .header("authorization", "auth_value");
+                then.status(200)
+                    .header("content-type", "application/x-parquet")
+                    .body("PAR1");
+            })
+            .await;
+        let app = Router::new()
+            .route("/table/{run}/{stream}/{table}", get(table_handler))
+            .with_state(TiledClient::for_mock_server(&server));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/table/run/primary/internal?column=time&column=stage-x&format=parquet")
+                    .header("Authorization", "auth_value")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/x-parquet");
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            "PAR1"
+        );
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn table_download_forwards_accept() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/table/full/run/primary/internal")
+                    .query_param_missing("column")
+                    .query_param_missing("format")
+                    .header("accept", "application/vnd.apache.arrow.file");
+                then.status(200).body("arrow");
+            })
+            .await;
+        let app = Router::new()
+            .route("/table/{run}/{stream}/{table}", get(table_handler))
+            .with_state(TiledClient::for_mock_server(&server));
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/table/run/primary/internal")
+                    .header("Accept", "application/vnd.apache.arrow.file")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        mock.assert();
+    }
+    fn download_app(server: &MockServer, signer: Option<UrlSigner>) -> Router {
+        Router::new()
+            .route("/asset/{run}/{stream}/{det}/{id}", get(download_handler))
+            .with_state(TiledClient::for_mock_server(server))
+            .layer(Extension(signer))
+    }
+    #[tokio::test]
+    async fn signed_download_forwards_credential() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/asset/bytes/run/primary/det")
+                    .query_param("id", "18")
+                    .header("authorization", "Bearer abc123");
+                then.status(200).body("data");
+            })
+            .await;
+        let signer = UrlSigner::new("secret", Duration::from_secs(900));
+        let auth = AuthHeader::from(HeaderValue::from_static("Bearer abc123"));
+        let signature = signer.sign("asset/run/primary/det/18", Some(&auth));
+        let uri = format!(
+            "/asset/run/primary/det/18?expires={}&token={}&signature={}",
+            signature.expires,
+            signature.token.unwrap(),
+            signature.signature
+        );
+        let response = download_app(&server, Some(signer))
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn invalid_signed_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/asset/bytes/run/primary/det");
+                then.status(200).body("data");
+            })
+            .await;
+        let signer = UrlSigner::new("secret", Duration::from_secs(900));
+        let signature = signer.sign("asset/run/primary/det/18", None);
+        // Signed for a different file
+        let uri = format!(
+            "/asset/run/primary/det/19?expires={}&signature={}",
+            signature.expires, signature.signature
+        );
+        let response = download_app(&server, Some(signer))
+            .oneshot(Request::builder().uri(&uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        // Signed links are refused if signing is not configured
+        let response = download_app(&server, None)
+            .oneshot(Request::builder().uri(&uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 403);
+        mock.assert_calls(0);
+    }
+    #[tokio::test]
+    async fn ranged_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/asset/bytes/run/primary/det")
+                    .query_param("id", "18")
+                    .header("range", "bytes=4-7")
+                    .header("if-range", "\"abc\"");
+                then.status(206)
+                    .header("content-range", "bytes 4-7/10")
+                    .header("accept-ranges", "bytes")
+                    .header("etag", "\"abc\"")
+                    .body("4567");
+            })
+            .await;
+        let response = download_app(&server, None)
+            .oneshot(
+                Request::builder()
+                    .uri("/asset/run/primary/det/18")
+                    .header("Range", "bytes=4-7")
+                    .header("If-Range", "\"abc\"")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.headers()["content-range"], "bytes 4-7/10");
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        assert_eq!(response.headers()["etag"], "\"abc\"");
+        assert_eq!(
+            response.into_body().collect().await.unwrap().to_bytes(),
+            "4567"
+        );
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn not_modified_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/asset/bytes/run/primary/det")
+                    .header("if-none-match", "\"abc\"");
+                then.status(304).header("etag", "\"abc\"");
+            })
+            .await;
+        let response = download_app(&server, None)
+            .oneshot(
+                Request::builder()
+                    .uri("/asset/run/primary/det/18")
+                    .header("If-None-Match", "\"abc\"")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 304);
+        assert_eq!(response.headers()["etag"], "\"abc\"");
+        mock.assert();
+    }
+    #[tokio::test]
+    async fn head_download() {
+        let server = MockServer::start();
+        let mock = server
+            .mock_async(|when, then| {
+                when.method("HEAD")
+                    .path("/api/v1/asset/bytes/run/primary/det");
+                then.status(200)
+                    .header("content-type", "application/x-hdf5")
+                    .header("accept-ranges", "bytes")
+                    .body("0123456789");
+            })
+            .await;
+        let response = download_app(&server, None)
+            .oneshot(
+                Request::builder()
+                    .method("HEAD")
+                    .uri("/asset/run/primary/det/18")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-length"], "10");
+        assert_eq!(response.headers()["accept-ranges"], "bytes");
+        assert!(
+            response
+                .into_body()
+                .collect()
+                .await
+                .unwrap()
+                .to_bytes()
+                .is_empty()
+        );
+        mock.assert();
+    }
+
+    /// Mock the requests made to tiled when archiving run `run`. Its one file is returned with
+    /// the given status.
+    async fn archive_mocks(server: &MockServer, file_status: u16) -> httpmock::Mock<'_> {
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/metadata/run");
+                then.status(200)
+                    .body_from_file("resources/metadata_run.json");
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/run");
+                then.status(200)
+                    .body_from_file("resources/search_run_container.json");
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/run/primary")
+                    .query_param("include_data_sources", "true");
+                then.status(200)
+                    .body_from_file("resources/search_event_stream.json");
+            })
+            .await;
+        server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/asset/bytes/run/primary/det")
+                    .query_param("id", "18")
+                    .header("authorization", "auth_value");
+                then.status(file_status).body("detector data");
+            })
+            .await
+    }
+    async fn archive(server: &MockServer, uri: &str) -> axum::response::Response {
+        Router::new()
+            .route("/run/{run}/archive", get(archive_handler))
+            .with_state(TiledClient::for_mock_server(server))
+            .oneshot(
+                Request::builder()
+                    .uri(uri)
+                    .header("Authorization", "auth_value")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+    /// The name and contents of each file in a zip archive
+    async fn zip_entries(body: &[u8]) -> Vec<(String, String)> {
+        let mut zip = ZipArchiveReader::open(futures::io::Cursor::new(body))
+            .await
+            .unwrap();
+        let mut entries = vec![];
+        for index in 0..zip.cdrs().len() {
+            let name = zip.cdrs()[index]
+                .insecure_file_name
+                .as_str()
+                .unwrap()
+                .to_owned();
+            let mut data = String::new();
+            let mut file = zip.file(index).await.unwrap();
+            futures::io::AsyncReadExt::read_to_string(&mut file, &mut data)
+                .await
+                .unwrap();
+            entries.push((name, data));
+        }
+        entries
+    }
+    #[tokio::test]
+    async fn tar_archive() {
+        let server = MockServer::start();
+        let asset = archive_mocks(&server, 200).await;
+        let response = archive(&server, "/run/run/archive?format=tar").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/x-tar");
+        assert_eq!(
+            response.headers()["content-disposition"],
+            "attachment; filename=\"run.tar\""
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        let mut archive = tokio_tar::Archive::new(&body[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut contents = vec![];
+        while let Some(entry) = entries.next().await {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut data = String::new();
+            entry.read_to_string(&mut data).await.unwrap();
+            contents.push((path, data));
+        }
+        assert_eq!(contents.len(), 2);
+        assert_eq!(
+            contents[0],
+            ("primary/det/adsim-2-det.h5".into(), "detector data".into())
+        );
+        assert_eq!(contents[1].0, "manifest.json");
+        let manifest: Value = serde_json::from_str(&contents[1].1).unwrap();
+        assert_eq!(manifest["run"], "run");
+        assert_eq!(manifest["metadata"]["start"]["scan_id"], 49);
+        assert_eq!(manifest["files"][0]["path"], "primary/det/adsim-2-det.h5");
+        assert_eq!(manifest["skipped"], json!([]));
+        asset.assert();
+    }
+    #[tokio::test]
+    async fn zip_archive() {
+        let server = MockServer::start();
+        let asset = archive_mocks(&server, 200).await;
+        let response = archive(&server, "/run/run/archive?stream=primary&detector=det").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-disposition"],
+            "attachment; filename=\"run.zip\""
+        );
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        let entries = zip_entries(&body).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            ("primary/det/adsim-2-det.h5".into(), "detector data".into())
+        );
+        assert_eq!(entries[1].0, "manifest.json");
+        asset.assert();
+    }
+    #[tokio::test]
+    async fn zip_archive_filtered() {
+        let server = MockServer::start();
+        let asset = archive_mocks(&server, 200).await;
+        let response = archive(&server, "/run/run/archive?detector=other").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()["content-type"], "application/zip");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        let entries = zip_entries(&body).await;
+        assert_eq!(
+            entries.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            ["manifest.json"]
+        );
+        asset.assert_calls(0);
+    }
+    #[tokio::test]
+    async fn zip_archive_skips_failed_files() {
+        let server = MockServer::start();
+        let asset = archive_mocks(&server, 403).await;
+        let response = archive(&server, "/run/run/archive?format=zip").await;
+        assert_eq!(response.status(), 200);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+
+        let entries = zip_entries(&body).await;
+        assert_eq!(entries.len(), 1);
+        let manifest: Value = serde_json::from_str(&entries[0].1).unwrap();
+        assert_eq!(manifest["files"], json!([]));
+        assert_eq!(
+            manifest["skipped"][0]["data_uri"],
+            "file://localhost/home/abi/data/adsim-2-det.h5"
+        );
+        asset.assert();
+    }
+    #[tokio::test]
+    async fn archive_of_unknown_run() {
+        let server = MockServer::start();
+        server
+            .mock_async(|when, then| {
+                when.path("/api/v1/metadata/missing");
+                then.status(404).body(r#"{"detail": "No such entry"}"#);
+            })
+            .await;
+        let response = archive(&server, "/run/missing/archive").await;
+        assert_eq!(response.status(), 404);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "NOT_FOUND");
+    }
+    #[tokio::test]
+    async fn archive_format_invalid() {
+        let server = MockServer::start();
+        let response = archive(&server, "/run/run/archive?format=rar").await;
+        assert_eq!(response.status(), 400);
+    }
 }
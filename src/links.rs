@@ -0,0 +1,192 @@
+use std::net::SocketAddr;
+
+use axum::http::HeaderMap;
+use axum::http::header::{FORWARDED, HOST};
+use url::Url;
+
+/// Where glazed can be reached by clients, used to build links to its own endpoints
+#[derive(Debug, Clone)]
+pub struct LinkBase {
+    public_address: Option<Url>,
+    bind_address: SocketAddr,
+}
+
+impl LinkBase {
+    pub fn new(public_address: Option<Url>, bind_address: SocketAddr) -> Self {
+        Self {
+            public_address,
+            bind_address,
+        }
+    }
+
+    /// The address clients used to reach glazed. The configured public address is used if there
+    /// is one. Otherwise it is taken from the `Forwarded` or `X-Forwarded-*` headers added by a
+    /// proxy, then the `Host` header, and finally the address glazed is bound to.
+    pub fn resolve(&self, headers: &HeaderMap) -> PublicUrl {
+        if let Some(address) = &self.public_address {
+            return PublicUrl(address.clone());
+        }
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                // Proxies append to these headers so the first value is from the outermost one
+                .and_then(|value| value.split(',').next())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+        let forwarded = header(FORWARDED.as_str()).map(parse_forwarded);
+        let (fwd_proto, fwd_host) = forwarded.unwrap_or_default();
+        let proto = fwd_proto
+            .or_else(|| header("x-forwarded-proto").map(String::from))
+            .unwrap_or_else(|| "http".into());
+        let host = fwd_host
+            .or_else(|| header("x-forwarded-host").map(String::from))
+            .or_else(|| header(HOST.as_str()).map(String::from))
+            .unwrap_or_else(|| self.bind_address.to_string());
+        let prefix = header("x-forwarded-prefix").unwrap_or_default();
+
+        Url::parse(&format!("{proto}://{host}/{}", prefix.trim_matches('/')))
+            .map(PublicUrl)
+            .unwrap_or_else(|_| self.fallback())
+    }
+
+    fn fallback(&self) -> PublicUrl {
+        PublicUrl(
+            Url::parse(&format!("http://{}/", self.bind_address)).expect("Socket address is valid"),
+        )
+    }
+}
+
+/// Read the `proto` and `host` of the first element of a `Forwarded` header (RFC 7239)
+fn parse_forwarded(element: &str) -> (Option<String>, Option<String>) {
+    let mut proto = None;
+    let mut host = None;
+    for pair in element.split(';') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"').to_owned();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "proto" => proto = Some(value),
+            "host" => host = Some(value),
+            _ => {}
+        }
+    }
+    (proto, host)
+}
+
+/// The base URL of glazed as seen by the client making the current request
+#[derive(Debug, Clone, PartialEq)]
+pub struct PublicUrl(Url);
+
+impl PublicUrl {
+    /// An absolute URL for the given path below the base URL. Segments are percent encoded.
+    pub fn url<'s>(&self, segments: impl IntoIterator<Item = &'s str>) -> Url {
+        let mut url = self.0.clone();
+        url.path_segments_mut()
+            .expect("Public URL is a valid base")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::http::HeaderMap;
+
+    use super::LinkBase;
+
+    fn links(public_address: Option<&str>) -> LinkBase {
+        LinkBase::new(
+            public_address.map(|addr| addr.parse().unwrap()),
+            SocketAddr::from(([0, 0, 0, 0], 3000)),
+        )
+    }
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(k, v)| (k.parse().unwrap(), v.parse().unwrap()))
+            .collect()
+    }
+
+    fn link(links: &LinkBase, headers: HeaderMap) -> String {
+        links
+            .resolve(&headers)
+            .url(["asset", "run id", "1"])
+            .to_string()
+    }
+
+    #[test]
+    fn public_address_takes_priority() {
+        let links = links(Some("https://example.com/glazed/"));
+        assert_eq!(
+            link(
+                &links,
+                headers(&[("x-forwarded-host", "proxy.example.com")])
+            ),
+            "https://example.com/glazed/asset/run%20id/1"
+        );
+    }
+
+    #[test]
+    fn forwarded_header() {
+        let links = links(None);
+        assert_eq!(
+            link(
+                &links,
+                headers(&[
+                    (
+                        "forwarded",
+                        r#"for=192.0.2.60;proto=https;host="example.com", for=10.0.0.1"#
+                    ),
+                    ("host", "glazed:3000")
+                ])
+            ),
+            "https://example.com/asset/run%20id/1"
+        );
+    }
+
+    #[test]
+    fn x_forwarded_headers() {
+        let links = links(None);
+        assert_eq!(
+            link(
+                &links,
+                headers(&[
+                    ("x-forwarded-proto", "https"),
+                    ("x-forwarded-host", "example.com, internal"),
+                    ("x-forwarded-prefix", "/glazed"),
+                    ("host", "glazed:3000"),
+                ])
+            ),
+            "https://example.com/glazed/asset/run%20id/1"
+        );
+    }
+
+    #[test]
+    fn host_header() {
+        let links = links(None);
+        assert_eq!(
+            link(&links, headers(&[("host", "glazed:3000")])),
+            "http://glazed:3000/asset/run%20id/1"
+        );
+    }
+
+    #[test]
+    fn bind_address_fallback() {
+        let links = links(None);
+        assert_eq!(
+            link(&links, HeaderMap::new()),
+            "http://0.0.0.0:3000/asset/run%20id/1"
+        );
+        assert_eq!(
+            link(&links, headers(&[("host", "not a host")])),
+            "http://0.0.0.0:3000/asset/run%20id/1"
+        );
+    }
+}
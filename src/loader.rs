@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use async_graphql::ErrorExtensions;
+use async_graphql::dataloader::{DataLoader, HashMapCache, Loader};
+use futures::future::join_all;
+use serde_json::Value;
+
+use crate::clients::{ClientError, RequestLimit, TiledClient};
+use crate::handlers::AuthHeader;
+use crate::model::{node, table};
+
+/// Loader for requests to tiled made while resolving a single GraphQL query. Identical requests
+/// are only sent once and the response is shared between every field that needs it.
+pub type TiledDataLoader = DataLoader<TiledLoader, HashMapCache>;
+
+/// The outcome of a single request. Errors are kept per request so that one failure does not
+/// affect the other requests batched alongside it.
+pub type Loaded<T> = Result<Arc<T>, Arc<ClientError>>;
+
+/// A search request. The auth header is part of the key so that responses are never shared
+/// between callers with different permissions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SearchKey {
+    pub path: String,
+    pub query: Vec<(&'static str, String)>,
+    pub auth: Option<AuthHeader>,
+    /// Whether the results are known not to change (eg the run has finished) and can be cached
+    /// between queries
+    pub immutable: bool,
+    /// Request a single page of results instead of following tiled's pagination
+    pub page: Option<Page>,
+}
+
+/// The offset and size of a single page of search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// A request for the contents of a table, either in full or a single partition
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TableKey {
+    pub path: String,
+    pub columns: Option<Vec<String>>,
+    pub partition: Option<usize>,
+    pub auth: Option<AuthHeader>,
+}
+
+/// A request for the values of an array, optionally sliced
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ArrayKey {
+    pub path: String,
+    pub slice: Option<String>,
+    pub auth: Option<AuthHeader>,
+}
+
+pub struct TiledLoader {
+    client: TiledClient,
+    limit: RequestLimit,
+    /// Searches whose invalid entries have already been reported in this query
+    reported: Mutex<HashSet<SearchKey>>,
+}
+
+impl TiledLoader {
+    /// Create a loader for a single GraphQL query. Requests made through it share the client's
+    /// concurrency limit.
+    pub fn data_loader(client: TiledClient) -> TiledDataLoader {
+        let limit = client.request_limit();
+        DataLoader::with_cache(
+            TiledLoader {
+                client,
+                limit,
+                reported: Mutex::default(),
+            },
+            tokio::spawn,
+            HashMapCache::default(),
+        )
+    }
+    /// Whether this is the first time the invalid entries of a search are being reported. Each
+    /// field sharing the search's response would otherwise report them again.
+    pub fn first_report(&self, key: &SearchKey) -> bool {
+        self.reported
+            .lock()
+            .expect("Reported searches lock is not poisoned")
+            .insert(key.clone())
+    }
+}
+
+impl Loader<SearchKey> for TiledLoader {
+    type Value = Loaded<node::Root>;
+    type Error = Infallible;
+
+    async fn load(
+        &self,
+        keys: &[SearchKey],
+    ) -> Result<HashMap<SearchKey, Self::Value>, Infallible> {
+        Ok(join_all(keys.iter().map(|key| async move {
+            let _permit = self.limit.acquire().await;
+            let query = key
+                .query
+                .iter()
+                .map(|(k, v)| (*k, Cow::from(v.as_str())))
+                .collect::<Vec<_>>();
+            let headers = key.auth.as_ref().map(AuthHeader::as_header_map);
+            let result = if let Some(page) = key.page {
+                self.client
+                    .search_page(&key.path, headers, &query, page.offset, page.limit)
+                    .await
+                    .map(Arc::new)
+            } else if key.immutable {
+                self.client
+                    .search_immutable(&key.path, headers, &query)
+                    .await
+            } else {
+                self.client
+                    .search(&key.path, headers, &query)
+                    .await
+                    .map(Arc::new)
+            };
+            (key.clone(), result.map_err(Arc::new))
+        }))
+        .await
+        .into_iter()
+        .collect())
+    }
+}
+
+impl Loader<TableKey> for TiledLoader {
+    type Value = Loaded<table::Table>;
+    type Error = Infallible;
+
+    async fn load(&self, keys: &[TableKey]) -> Result<HashMap<TableKey, Self::Value>, Infallible> {
+        Ok(join_all(keys.iter().map(|key| async move {
+            let _permit = self.limit.acquire().await;
+            let headers = key.auth.as_ref().map(AuthHeader::as_header_map);
+            let columns = key.columns.clone();
+            let result = match key.partition {
+                Some(partition) => {
+                    self.client
+                        .table_partition(&key.path, partition, columns, headers)
+                        .await
+                }
+                None => self.client.table_full(&key.path, columns, headers).await,
+            };
+            (key.clone(), result.map(Arc::new).map_err(Arc::new))
+        }))
+        .await
+        .into_iter()
+        .collect())
+    }
+}
+
+impl Loader<ArrayKey> for TiledLoader {
+    type Value = Loaded<Value>;
+    type Error = Infallible;
+
+    async fn load(&self, keys: &[ArrayKey]) -> Result<HashMap<ArrayKey, Self::Value>, Infallible> {
+        Ok(join_all(keys.iter().map(|key| async move {
+            let _permit = self.limit.acquire().await;
+            let headers = key.auth.as_ref().map(AuthHeader::as_header_map);
+            let result = self
+                .client
+                .array_full(&key.path, key.slice.as_deref(), headers)
+                .await;
+            (key.clone(), result.map(Arc::new).map_err(Arc::new))
+        }))
+        .await
+        .into_iter()
+        .collect())
+    }
+}
+
+/// Load a single request, waiting for any identical request already in progress
+pub async fn load<K, T>(loader: &TiledDataLoader, key: K) -> async_graphql::Result<Arc<T>>
+where
+    K: Send + Sync + std::hash::Hash + Eq + Clone + 'static,
+    TiledLoader: Loader<K, Value = Loaded<T>, Error = Infallible>,
+{
+    let Ok(loaded) = loader.load_one(key).await;
+    loaded
+        .expect("Loader returns a value for every key")
+        .map_err(|e| e.extend())
+}
@@ -1,15 +1,22 @@
+use std::time::Duration;
+
 use async_graphql::{EmptyMutation, EmptySubscription, Schema};
 use axum::http::StatusCode;
 use axum::response::{Html, IntoResponse};
 use axum::routing::{get, post};
 use axum::{Extension, Router};
 
+mod archive;
+mod cache;
 mod cli;
 mod clients;
 mod config;
 mod download;
 mod handlers;
+mod links;
+mod loader;
 mod model;
+mod signing;
 #[cfg(test)]
 mod test_utils;
 
@@ -18,10 +25,15 @@ use tokio::select;
 use tokio::signal::unix::{SignalKind, signal};
 use tracing::info;
 
-use crate::clients::TiledClient;
+use crate::clients::{RetryPolicy, TiledClient};
 use crate::config::GlazedConfig;
-use crate::handlers::{download_handler, graphiql_handler, graphql_handler};
+use crate::handlers::{
+    archive_handler, array_handler, download_handler, graphiql_handler, graphql_handler,
+    table_handler,
+};
+use crate::links::LinkBase;
 use crate::model::TiledQuery;
+use crate::signing::UrlSigner;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -45,24 +57,61 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn serve(config: GlazedConfig) -> Result<(), Box<dyn std::error::Error>> {
-    let client = TiledClient::new(config.tiled_client.address);
-    let schema = Schema::build(TiledQuery, EmptyMutation, EmptySubscription)
-        .data(config.bind_address)
-        .data(client.clone())
-        .finish();
+    let client = TiledClient::new(config.tiled_client.address)
+        .with_max_pages(config.tiled_client.max_pages)
+        .with_max_concurrent_requests(config.tiled_client.max_concurrent_requests)
+        .with_max_array_elements(config.tiled_client.max_array_elements)
+        .with_cache(
+            config.tiled_client.cache.capacity,
+            Duration::from_secs(config.tiled_client.cache.ttl_seconds),
+        )
+        .with_timeouts(
+            Duration::from_millis(config.tiled_client.connect_timeout_ms),
+            Duration::from_millis(config.tiled_client.read_timeout_ms),
+        )
+        .with_retries(RetryPolicy {
+            max_retries: config.tiled_client.retry.max_retries,
+            initial_backoff: Duration::from_millis(config.tiled_client.retry.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.tiled_client.retry.max_backoff_ms),
+        });
+    let cache = &config.tiled_client.cache;
+    if cache.capacity > 0 && cache.stats_interval_seconds > 0 {
+        tokio::spawn(
+            client
+                .clone()
+                .log_cache_stats(Duration::from_secs(cache.stats_interval_seconds)),
+        );
+    }
+    let signer = config
+        .signed_links
+        .map(|signing| UrlSigner::new(&signing.key, Duration::from_secs(signing.lifetime_seconds)));
+    let mut schema =
+        Schema::build(TiledQuery, EmptyMutation, EmptySubscription).data(client.clone());
+    if let Some(signer) = &signer {
+        schema = schema.data(signer.clone());
+    }
+    let schema = schema.finish();
 
-    let graphql_endpoint = config.public_address.map(|u| u.to_string());
+    let graphql_endpoint = config.public_address.as_ref().map(|u| u.to_string());
 
     let app = Router::new()
         .route("/graphql", post(graphql_handler).get(graphql_get_warning))
         .route("/graphiql", get(|| graphiql_handler(graphql_endpoint)))
         .route("/asset/{run}/{stream}/{det}/{id}", get(download_handler))
+        .route("/array/{*path}", get(array_handler))
+        .route("/table/{run}/{stream}/{table}", get(table_handler))
+        .route("/run/{run}/archive", get(archive_handler))
         .with_state(client)
         .fallback((
             StatusCode::NOT_FOUND,
             Html(include_str!("../static/404.html")),
         ))
-        .layer(Extension(schema));
+        .layer(Extension(schema))
+        .layer(Extension(signer))
+        .layer(Extension(LinkBase::new(
+            config.public_address,
+            config.bind_address,
+        )));
 
     let listener = tokio::net::TcpListener::bind(config.bind_address).await?;
     info!("Serving glazed at {:?}", config.bind_address);
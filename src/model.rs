@@ -2,20 +2,28 @@ pub(crate) mod app;
 pub(crate) mod array;
 pub(crate) mod container;
 pub(crate) mod event_stream;
+pub(crate) mod filter;
 pub(crate) mod node;
 pub(crate) mod run;
+pub(crate) mod sort;
 pub(crate) mod table;
 
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::sync::Arc;
 
-use async_graphql::{Context, Object, Result, Union};
+use async_graphql::connection::{Connection, Edge, query};
+use async_graphql::{Context, ErrorExtensions, Object, Result, ResultExt, SimpleObject, Union};
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
 use serde_json::Value;
-use tracing::{info, instrument};
+use tracing::{info, instrument, warn};
 
 use crate::clients::TiledClient;
-use crate::handlers::AuthHeader;
+use crate::handlers::{AuthHeader, asset_path};
+use crate::links::PublicUrl;
+use crate::loader::{ArrayKey, Page, SearchKey, TableKey, TiledDataLoader, load};
 use crate::model::node::NodeAttributes;
+use crate::signing::UrlSigner;
 
 pub(crate) struct TiledQuery;
 
@@ -23,7 +31,7 @@ pub(crate) struct TiledQuery;
 impl TiledQuery {
     #[instrument(skip(self, ctx))]
     async fn app_metadata(&self, ctx: &Context<'_>) -> Result<app::AppMetadata> {
-        Ok(ctx.data::<TiledClient>()?.app_metadata().await?)
+        ctx.data::<TiledClient>()?.app_metadata().await.extend()
     }
 
     async fn instrument_session(&self, name: String) -> InstrumentSession {
@@ -40,31 +48,157 @@ impl InstrumentSession {
     async fn name(&self) -> &str {
         &self.name
     }
-    async fn runs(&self, ctx: &Context<'_>) -> Result<Vec<Run>> {
+    #[allow(clippy::too_many_arguments)]
+    async fn runs(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<filter::RunFilter>,
+        sort: Option<Vec<sort::RunSort>>,
+    ) -> Result<Connection<usize, Run, RunConnectionFields>> {
         let auth = ctx.data::<Option<AuthHeader>>()?;
-        let headers = auth.as_ref().map(AuthHeader::as_header_map);
-        let root = ctx
-            .data::<TiledClient>()?
-            .search(
-                "",
-                headers,
-                &[
-                    (
-                        "filter[eq][condition][key]",
-                        "start.instrument_session".into(),
-                    ),
-                    (
-                        "filter[eq][condition][value]",
-                        format!(r#""{}""#, self.name).into(),
-                    ),
-                    ("include_data_sources", "true".into()),
-                ],
-            )
-            .await?;
-        Ok(root.into_data().map(|d| Run { data: d }).collect())
+        let loader = ctx.data::<TiledDataLoader>()?;
+        let look_ahead = ctx.look_ahead();
+        let run_selections = [
+            look_ahead.field("nodes"),
+            look_ahead.field("edges").field("node"),
+        ];
+        // If only the count is needed, there is no need to fetch any runs
+        let count_only = !(look_ahead.field("nodes").exists()
+            || look_ahead.field("edges").exists()
+            || look_ahead.field("pageInfo").exists());
+        let mut params = vec![
+            (
+                "filter[eq][condition][key]",
+                "start.instrument_session".into(),
+            ),
+            (
+                "filter[eq][condition][value]",
+                format!(r#""{}""#, self.name).into(),
+            ),
+        ];
+        params.extend(RUN_FIELDS.map(|f| ("fields", f.into())));
+        // The full documents are only needed if the query reads them
+        let full_metadata = run_selections.iter().any(|r| {
+            r.field("metadata").exists() || r.field("start").exists() || r.field("stop").exists()
+        });
+        if !full_metadata {
+            params.push(("select_metadata", RUN_SUMMARY.into()));
+        }
+        if run_selections
+            .iter()
+            .any(|r| r.field("streamSorting").exists())
+        {
+            params.push(("fields", "sorting".into()));
+        }
+        if let Some(filter) = filter {
+            params.extend(filter.query_params());
+        }
+        let sort = sort.unwrap_or_default();
+        if !sort.is_empty() {
+            params.push(("sort", sort::RunSort::query_param(&sort).into()));
+        }
+        let sorting = if !sort.is_empty() {
+            sort.iter().map(sort::RunSort::as_sorting).collect()
+        } else if look_ahead.field("sorting").exists() {
+            // Runs are in the default order of the container they are searched in
+            ctx.data::<TiledClient>()?
+                .sorting("", auth.as_ref().map(AuthHeader::as_header_map))
+                .await
+                .extend()?
+        } else {
+            vec![]
+        };
+        let search = |offset, limit| SearchKey {
+            path: String::new(),
+            query: params
+                .iter()
+                .map(|(k, v)| (*k, v.clone().into_owned()))
+                .collect(),
+            auth: auth.clone(),
+            // New runs can be added to a session at any time
+            immutable: false,
+            page: Some(Page { offset, limit }),
+        };
+        query(
+            after,
+            before,
+            first,
+            last,
+            |after, before, first, last| async move {
+                let mut start = after.map_or(0, |a: usize| a + 1);
+                let mut end: Option<usize> = before;
+                if end.is_none() && last.is_some() {
+                    // Counting from the end needs to know where the end is
+                    end = load(loader, search(0, Some(0))).await?.count();
+                }
+                if let Some(first) = first {
+                    end = Some(end.map_or(start + first, |e| e.min(start + first)));
+                }
+                if let (Some(last), Some(e)) = (last, end) {
+                    start = start.max(e.saturating_sub(last));
+                }
+                let limit = if count_only {
+                    Some(0)
+                } else {
+                    end.map(|e| e.saturating_sub(start).min(MAX_PAGE_SIZE))
+                };
+
+                let key = search(start, limit);
+                let root = load(loader, key.clone()).await?;
+                report_invalid(ctx, &key, &root)?;
+                let total_count = root.count();
+                let has_next = match total_count {
+                    Some(total) => start + root.page_len() < total,
+                    None => root.has_next(),
+                };
+                let mut connection = Connection::with_additional_fields(
+                    start > 0,
+                    has_next,
+                    RunConnectionFields {
+                        total_count,
+                        sorting,
+                    },
+                );
+                connection.edges.extend(
+                    Arc::unwrap_or_clone(root)
+                        .into_indexed_data()
+                        .map(|(i, data)| Edge::new(start + i, Run { data })),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
     }
 }
 
+/// Largest page of runs that will be requested from tiled in one go
+const MAX_PAGE_SIZE: usize = 300;
+
+/// The parts of each run's tiled entry that are always needed. Others are only requested if
+/// the query selects fields that depend on them.
+const RUN_FIELDS: [&str; 4] = ["metadata", "structure_family", "structure", "specs"];
+
+/// JMESPath projection of a run's metadata keeping only the fields needed to identify it and
+/// to tell whether, and when, it finished
+const RUN_SUMMARY: &str = "{\
+    start: {uid: start.uid, time: start.time, instrument_session: start.instrument_session, \
+        scan_id: start.scan_id}, \
+    stop: stop && {uid: stop.uid, time: stop.time, run_start: stop.run_start, \
+        exit_status: stop.exit_status}}";
+
+#[derive(SimpleObject)]
+struct RunConnectionFields {
+    /// The total number of runs matching the query
+    total_count: Option<usize>,
+    /// The order of the runs: the sort requested or, if none was, tiled's default order for the
+    /// catalog being searched
+    sorting: Vec<node::Sorting>,
+}
+
 #[derive(Union)]
 enum RunData<'run> {
     Array(ArrayData<'run>),
@@ -75,6 +209,7 @@ struct ArrayData<'run> {
     run: &'run Run,
     id: String,
     stream: String,
+    data_key: Option<Box<event_stream::DataKey>>,
     attrs: node::Attributes<HashMap<String, Value>, array::ArrayStructure>,
 }
 
@@ -83,6 +218,23 @@ impl<'run> ArrayData<'run> {
     async fn name(&self) -> &str {
         &self.id
     }
+    /// The name of the stream this array belongs to
+    async fn stream(&self) -> &str {
+        &self.stream
+    }
+    /// The stream's description of this array
+    async fn data_key(&self) -> Option<&event_stream::DataKey> {
+        self.data_key.as_deref()
+    }
+    /// The shape, chunking and type of this array
+    async fn structure(&self) -> &array::ArrayStructure {
+        &self.attrs.structure
+    }
+    /// The values of this array as nested lists, optionally sliced using numpy syntax, eg
+    /// `0,::2,100:200`. Requests for more elements than the server allows are refused.
+    async fn values(&self, ctx: &Context<'_>, slice: Option<String>) -> Option<Result<Value>> {
+        Some(self.inner_values(ctx, slice).await)
+    }
     async fn files<'ad>(&'ad self) -> Vec<Asset<'ad>> {
         self.attrs
             .data_sources
@@ -98,6 +250,46 @@ impl<'run> ArrayData<'run> {
     }
 }
 
+impl ArrayData<'_> {
+    async fn inner_values(&self, ctx: &Context<'_>, slice: Option<String>) -> Result<Value> {
+        let structure = &self.attrs.structure;
+        let elements = match slice.as_deref() {
+            Some(slice) => structure.sliced_elements(slice).map_err(|msg| {
+                async_graphql::Error::new(msg)
+                    .extend_with(|_, ext| ext.set("code", "INVALID_SLICE"))
+            })?,
+            None => structure.num_elements(),
+        };
+        let limit = ctx.data::<TiledClient>()?.max_array_elements();
+        if elements > limit {
+            return Err(async_graphql::Error::new(format!(
+                "Requested {elements} elements but at most {limit} can be read at once"
+            ))
+            .extend_with(|_, ext| {
+                ext.set("code", "ARRAY_TOO_LARGE");
+                ext.set("elements", elements);
+                ext.set("limit", limit);
+            }));
+        }
+        let key = ArrayKey {
+            path: self.path(),
+            slice,
+            auth: ctx.data::<Option<AuthHeader>>()?.clone(),
+        };
+        let values = load(ctx.data::<TiledDataLoader>()?, key).await?;
+        Ok(Arc::unwrap_or_clone(values))
+    }
+    fn path(&self) -> String {
+        self.attrs
+            .ancestors
+            .iter()
+            .chain([&self.id])
+            .map(|s| s.as_str())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
 struct Asset<'a> {
     asset: &'a node::Asset,
     data: &'a ArrayData<'a>,
@@ -108,18 +300,34 @@ impl Asset<'_> {
     async fn file(&self) -> &str {
         &self.asset.data_uri
     }
+    /// A link to download the file through glazed. If links are signed, it can be used without
+    /// an Authorization header until it expires.
     async fn download(&self, ctx: &Context<'_>) -> Option<String> {
-        let id = self.asset.id?;
-        let base = ctx.data::<SocketAddr>().ok()?;
-        Some(format!(
-            "{}/asset/{}/{}/{}/{}",
-            base, self.data.run.data.id, self.data.stream, self.data.id, id
-        ))
+        let path = asset_path(
+            &self.data.run.data.id,
+            &self.data.stream,
+            &self.data.id,
+            self.asset.id?,
+        );
+        let mut url = ctx.data::<PublicUrl>().ok()?.url(path.split('/'));
+        if let Some(signer) = ctx.data_opt::<UrlSigner>() {
+            let auth = ctx.data::<Option<AuthHeader>>().ok()?;
+            let signature = signer.sign(&path, auth.as_ref());
+            let mut query = url.query_pairs_mut();
+            query.append_pair("expires", &signature.expires.to_string());
+            if let Some(token) = &signature.token {
+                query.append_pair("token", token);
+            }
+            query.append_pair("signature", &signature.signature);
+        }
+        Some(url.to_string())
     }
 }
 
 struct TableData {
+    stream: String,
     id: String,
+    data_keys: Vec<event_stream::DataKey>,
     attrs: node::Attributes<HashMap<String, Value>, table::TableStructure>,
 }
 
@@ -128,27 +336,113 @@ impl TableData {
     async fn name(&self) -> &str {
         &self.id
     }
+    /// The name of the stream this table belongs to
+    async fn stream(&self) -> &str {
+        &self.stream
+    }
+    /// The stream's description of each column. Columns added by tiled, such as `time` and
+    /// `seq_num`, have no description.
+    async fn data_keys(&self) -> &[event_stream::DataKey] {
+        &self.data_keys
+    }
     async fn columns(&self) -> &[String] {
         &self.attrs.structure.columns
     }
+    /// The number of partitions the table is split into. Each can be requested separately.
+    async fn npartitions(&self) -> i64 {
+        self.attrs.structure.npartitions
+    }
+    /// The name, arrow type and nullability of each column, decoded from the table's schema
+    async fn column_info(&self) -> Result<Vec<table::Column>> {
+        self.schema_columns()
+    }
+    /// The rows of the table, optionally limited to a single partition. `offset` and `limit`
+    /// select rows after the whole table (or partition) has been read from tiled, so they do not
+    /// reduce the amount fetched. For tables with more than one partition they can only be used
+    /// along with `partition`.
     async fn data(
         &self,
         ctx: &Context<'_>,
         columns: Option<Vec<String>>,
+        partition: Option<usize>,
+        #[graphql(default)] offset: usize,
+        limit: Option<usize>,
     ) -> Option<Result<HashMap<String, Vec<Value>>>> {
-        Some(self.inner_data(ctx, columns).await)
+        Some(
+            self.inner_data(ctx, columns, partition, offset, limit)
+                .await,
+        )
+    }
+    /// The same rows as `data`, with each column's values typed according to the table's
+    /// schema. Columns are in schema order.
+    async fn typed_data(
+        &self,
+        ctx: &Context<'_>,
+        columns: Option<Vec<String>>,
+        partition: Option<usize>,
+        #[graphql(default)] offset: usize,
+        limit: Option<usize>,
+    ) -> Option<Result<Vec<table::ColumnValues>>> {
+        Some(
+            self.inner_typed_data(ctx, columns, partition, offset, limit)
+                .await,
+        )
     }
 }
 
 impl TableData {
+    fn schema_columns(&self) -> Result<Vec<table::Column>> {
+        self.attrs.structure.column_info().map_err(|msg| {
+            async_graphql::Error::new(msg)
+                .extend_with(|_, ext| ext.set("code", "INVALID_TILED_RESPONSE"))
+        })
+    }
+    async fn inner_typed_data(
+        &self,
+        ctx: &Context<'_>,
+        columns: Option<Vec<String>>,
+        partition: Option<usize>,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<table::ColumnValues>> {
+        let info = self.schema_columns()?;
+        let mut data = self
+            .inner_data(ctx, columns, partition, offset, limit)
+            .await?;
+        Ok(info
+            .into_iter()
+            .filter_map(|col| {
+                let values = data.remove(&col.name)?;
+                Some(table::ColumnValues::new(col.name, col.kind, values))
+            })
+            .collect())
+    }
     async fn inner_data(
         &self,
         ctx: &Context<'_>,
         columns: Option<Vec<String>>,
+        partition: Option<usize>,
+        offset: usize,
+        limit: Option<usize>,
     ) -> Result<HashMap<String, Vec<Value>>> {
+        let npartitions = self.attrs.structure.npartitions;
+        match partition {
+            Some(partition) if partition as i64 >= npartitions => {
+                return Err(async_graphql::Error::new(format!(
+                    "Partition {partition} out of range for table with {npartitions} partitions"
+                ))
+                .extend_with(|_, ext| ext.set("code", "INVALID_PARTITION")));
+            }
+            // Selecting rows from the full table would still read every partition from tiled
+            None if npartitions > 1 && (offset > 0 || limit.is_some()) => {
+                return Err(async_graphql::Error::new(format!(
+                    "offset and limit require a partition for tables with {npartitions} partitions"
+                ))
+                .extend_with(|_, ext| ext.set("code", "PARTITION_REQUIRED")));
+            }
+            _ => {}
+        }
         let auth = ctx.data::<Option<AuthHeader>>()?;
-        let headers = auth.as_ref().map(AuthHeader::as_header_map);
-        let client = ctx.data::<TiledClient>()?;
         let p = self
             .attrs
             .ancestors
@@ -159,8 +453,20 @@ impl TableData {
             .join("/");
         info!("path: {:?}", p);
 
-        let table_data = client.table_full(&p, columns, headers).await?;
-        Ok(table_data)
+        let key = TableKey {
+            path: p,
+            columns,
+            partition,
+            auth: auth.clone(),
+        };
+        let table_data = load(ctx.data::<TiledDataLoader>()?, key).await?;
+        Ok(table_data
+            .iter()
+            .map(|(col, values)| {
+                let rows = values.iter().skip(offset).take(limit.unwrap_or(usize::MAX));
+                (col.clone(), rows.cloned().collect())
+            })
+            .collect())
     }
 }
 
@@ -171,70 +477,255 @@ struct Run {
 #[Object]
 impl Run {
     async fn scan_number(&self) -> Option<i64> {
+        self.start_doc().map(|sd| sd.scan_id)
+    }
+    async fn id(&self) -> &str {
+        &self.data.id
+    }
+    /// The document emitted by bluesky when the run started
+    async fn start(&self) -> Option<&run::Start> {
+        self.start_doc()
+    }
+    /// The document emitted by bluesky when the run finished, if it has
+    async fn stop(&self) -> Option<&run::Stop> {
+        self.stop_doc()
+    }
+    async fn start_time(&self) -> Option<DateTime<Utc>> {
+        self.start_doc().and_then(|sd| timestamp(sd.time))
+    }
+    async fn stop_time(&self) -> Option<DateTime<Utc>> {
+        self.stop_doc().and_then(|sd| timestamp(sd.time))
+    }
+    /// Time taken by the run in seconds, if it has finished
+    async fn duration(&self) -> Option<f64> {
+        Some(self.stop_doc()?.time - self.start_doc()?.time)
+    }
+    async fn is_complete(&self) -> bool {
+        self.stop_doc().is_some()
+    }
+    /// The run's start and stop documents as received from tiled, including any fields not
+    /// otherwise exposed
+    async fn metadata(&self) -> Option<&Value> {
         if let NodeAttributes::Container(attr) = &*self.data.attributes {
-            attr.metadata.start_doc().map(|sd| sd.scan_id)
+            attr.metadata.raw()
         } else {
             None
         }
     }
-    async fn id(&self) -> &str {
-        &self.data.id
+    /// The order of the streams within this run. This is not the run's position in the session;
+    /// see `sorting` on the run connection for that.
+    async fn stream_sorting(&self) -> Option<&[node::Sorting]> {
+        self.data.attributes.sorting()
+    }
+    /// The event streams recorded by this run
+    async fn streams(&self, ctx: &Context<'_>) -> Result<Vec<Stream<'_>>> {
+        self.stream_list(ctx).await
     }
+    async fn stream(&self, ctx: &Context<'_>, name: String) -> Result<Option<Stream<'_>>> {
+        Ok(self
+            .stream_list(ctx)
+            .await?
+            .into_iter()
+            .find(|stream| stream.data.id == name))
+    }
+    /// The arrays and tables from every stream in this run
     async fn data(&self, ctx: &Context<'_>) -> Result<Vec<RunData<'_>>> {
-        let auth = ctx.data::<Option<AuthHeader>>()?;
-        let headers = auth.as_ref().map(AuthHeader::as_header_map);
-        let client = ctx.data::<TiledClient>()?;
-        let run_data = client
-            .search(
-                &self.data.id,
-                headers.clone(),
-                &[("include_data_sources", "true".into())],
-            )
-            .await?;
-        let mut sources = Vec::new();
-        for stream in run_data.data() {
-            let stream_data = client
-                .search(
-                    &format!("{}/{}", self.data.id, stream.id),
-                    headers.clone(),
-                    &[("include_data_sources", "true".into())],
-                )
-                .await?;
-            for dataset in stream_data.into_data() {
-                match *dataset.attributes {
-                    NodeAttributes::Array(attrs) => sources.push(RunData::Array(ArrayData {
-                        run: self,
-                        stream: stream.id.clone(),
-                        id: dataset.id,
-                        attrs,
-                    })),
-                    NodeAttributes::Table(attrs) => sources.push(RunData::Internal(TableData {
-                        id: dataset.id,
-                        attrs,
-                    })),
-                    NodeAttributes::Container(_) => {}
-                }
-            }
+        let include_data_sources = ctx.look_ahead().field("files").exists();
+        let streams = self.stream_list(ctx).await?;
+        let contents = try_join_all(
+            streams
+                .iter()
+                .map(|stream| stream.contents(ctx, include_data_sources)),
+        )
+        .await?;
+        Ok(contents.into_iter().flatten().collect())
+    }
+}
+
+struct Stream<'run> {
+    run: &'run Run,
+    data: node::Data,
+}
+
+#[Object]
+impl<'run> Stream<'run> {
+    async fn name(&self) -> &str {
+        &self.data.id
+    }
+    async fn metadata(&self) -> Option<&event_stream::EventStreamMetadata> {
+        self.event_stream_metadata()
+    }
+    async fn data(&self, ctx: &Context<'_>) -> Result<Vec<RunData<'run>>> {
+        let include_data_sources = ctx.look_ahead().field("files").exists();
+        self.contents(ctx, include_data_sources).await
+    }
+    async fn arrays(&self, ctx: &Context<'_>) -> Result<Vec<ArrayData<'run>>> {
+        let include_data_sources = ctx.look_ahead().field("files").exists();
+        Ok(self
+            .contents(ctx, include_data_sources)
+            .await?
+            .into_iter()
+            .filter_map(|data| match data {
+                RunData::Array(array) => Some(array),
+                RunData::Internal(_) => None,
+            })
+            .collect())
+    }
+    async fn tables(&self, ctx: &Context<'_>) -> Result<Vec<TableData>> {
+        Ok(self
+            .contents(ctx, false)
+            .await?
+            .into_iter()
+            .filter_map(|data| match data {
+                RunData::Array(_) => None,
+                RunData::Internal(table) => Some(table),
+            })
+            .collect())
+    }
+}
+
+impl<'run> Stream<'run> {
+    fn event_stream_metadata(&self) -> Option<&event_stream::EventStreamMetadata> {
+        if let NodeAttributes::Container(attr) = &*self.data.attributes {
+            attr.metadata.event_stream()
+        } else {
+            None
         }
-        Ok(sources)
     }
+    /// The arrays and tables in this stream. Data sources are only used to list the files backing
+    /// each array so are only requested when needed.
+    async fn contents(
+        &self,
+        ctx: &Context<'_>,
+        include_data_sources: bool,
+    ) -> Result<Vec<RunData<'run>>> {
+        let path = format!("{}/{}", self.run.data.id, self.data.id);
+        let key = self.run.search_key(ctx, path, include_data_sources)?;
+        let stream_data = load(ctx.data::<TiledDataLoader>()?, key.clone()).await?;
+        report_invalid(ctx, &key, &stream_data)?;
+        let metadata = self.event_stream_metadata();
+        let data_key = |name: &str| metadata.and_then(|m| m.data_key(name));
+        Ok(Arc::unwrap_or_clone(stream_data)
+            .into_data()
+            .filter_map(|dataset| match *dataset.attributes {
+                NodeAttributes::Array(attrs) => Some(RunData::Array(ArrayData {
+                    run: self.run,
+                    stream: self.data.id.clone(),
+                    data_key: data_key(&dataset.id).map(Box::new),
+                    id: dataset.id,
+                    attrs,
+                })),
+                NodeAttributes::Table(attrs) => Some(RunData::Internal(TableData {
+                    stream: self.data.id.clone(),
+                    data_keys: attrs
+                        .structure
+                        .columns
+                        .iter()
+                        .filter_map(|col| data_key(col))
+                        .collect(),
+                    id: dataset.id,
+                    attrs,
+                })),
+                NodeAttributes::Container(_) => None,
+            })
+            .collect())
+    }
+}
+
+/// Report entries from a search that could not be parsed as errors against the current field,
+/// without failing the rest of the query. Entries are only reported by the first field to use
+/// the search.
+fn report_invalid(ctx: &Context<'_>, key: &SearchKey, root: &node::Root) -> Result<()> {
+    if !ctx.data::<TiledDataLoader>()?.loader().first_report(key) {
+        return Ok(());
+    }
+    for entry in root.invalid() {
+        warn!("Skipping invalid node {:?}: {}", entry.id, entry.error);
+        ctx.add_error(ctx.set_error_path(entry.extend().into_server_error(ctx.item.pos)));
+    }
+    Ok(())
+}
+
+impl Run {
+    fn search_key(
+        &self,
+        ctx: &Context<'_>,
+        path: String,
+        include_data_sources: bool,
+    ) -> Result<SearchKey> {
+        let query = if include_data_sources {
+            vec![("include_data_sources", "true".into())]
+        } else {
+            vec![]
+        };
+        Ok(SearchKey {
+            path,
+            query,
+            auth: ctx.data::<Option<AuthHeader>>()?.clone(),
+            // Once a run has stopped, its contents will not change
+            immutable: self.stop_doc().is_some(),
+            page: None,
+        })
+    }
+    async fn stream_list(&self, ctx: &Context<'_>) -> Result<Vec<Stream<'_>>> {
+        let key = self.search_key(ctx, self.data.id.clone(), false)?;
+        let run_data = load(ctx.data::<TiledDataLoader>()?, key.clone()).await?;
+        report_invalid(ctx, &key, &run_data)?;
+        Ok(run_data
+            .data()
+            .filter(|data| matches!(*data.attributes, NodeAttributes::Container(_)))
+            .map(|data| Stream {
+                run: self,
+                data: data.clone(),
+            })
+            .collect())
+    }
+    fn start_doc(&self) -> Option<&run::Start> {
+        if let NodeAttributes::Container(attr) = &*self.data.attributes {
+            attr.metadata.start_doc()
+        } else {
+            None
+        }
+    }
+    fn stop_doc(&self) -> Option<&run::Stop> {
+        if let NodeAttributes::Container(attr) = &*self.data.attributes {
+            attr.metadata.stop_doc()
+        } else {
+            None
+        }
+    }
+}
+
+/// Convert a bluesky document time (seconds since the epoch) into a timestamp
+fn timestamp(time: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_micros((time * 1e6).round() as i64)
 }
 
 #[cfg(test)]
 mod tests {
-    use async_graphql::{EmptyMutation, EmptySubscription, Schema, value};
-    use axum::http::HeaderValue;
-    use httpmock::MockServer;
+    use async_graphql::{EmptyMutation, EmptySubscription, PathSegment, Request, Schema, value};
+    use axum::http::{HeaderMap, HeaderValue};
+    use httpmock::{Mock, MockServer};
     use serde_json::json;
 
     use crate::TiledQuery;
     use crate::clients::TiledClient;
     use crate::handlers::AuthHeader;
+    use crate::links::LinkBase;
+    use crate::loader::TiledLoader;
+    use crate::model::RUN_SUMMARY;
 
     fn build_schema(url: &str) -> Schema<TiledQuery, EmptyMutation, EmptySubscription> {
+        build_schema_with_client(TiledClient::new(url.parse().unwrap()))
+    }
+
+    fn build_schema_with_client(
+        client: TiledClient,
+    ) -> Schema<TiledQuery, EmptyMutation, EmptySubscription> {
         Schema::build(TiledQuery, EmptyMutation, EmptySubscription)
             .data(Option::<AuthHeader>::None)
-            .data(TiledClient::new(url.parse().unwrap()))
+            .data(TiledLoader::data_loader(client.clone()))
+            .data(client)
             .finish()
     }
 
@@ -272,19 +763,68 @@ mod tests {
             .execute(
                 r#"{instrumentSession(name: "cm12345-2") {
                     runs {
-                        id
+                        totalCount
+                        nodes { id }
                     }
                 }}"#,
             )
             .await;
-        assert_eq!(response.errors, &[]);
+        assert_eq!(response.errors.len(), 1);
+        let error = &response.errors[0];
+        assert!(error.message.starts_with("Invalid tiled node: "));
+        assert_eq!(
+            error.extensions.as_ref().unwrap().get("code"),
+            Some(&value!("INVALID_TILED_RESPONSE"))
+        );
         assert_eq!(
             response.data,
-            value!({"instrumentSession": {"runs": [{"id": "1e37c0ed-e87e-470d-be18-9d7f62f69127"}]}})
+            value!({"instrumentSession": {"runs": {
+                "totalCount": 2,
+                "nodes": [{"id": "1e37c0ed-e87e-470d-be18-9d7f62f69127"}]
+            }}})
         );
         mock_root.assert_async().await;
     }
 
+    #[tokio::test]
+    async fn invalid_entries_reported_once() {
+        let server = MockServer::start();
+        let [_, _, mock_stream] = mock_run_searches(&server).await;
+        mock_stream.delete_async().await;
+        let mut stream: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/search_event_stream.json").unwrap(),
+        )
+        .unwrap();
+        stream["data"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"id": "broken", "attributes": {}}));
+        let mock_stream = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/run_id/primary");
+                then.status(200).json_body(stream);
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { stream(name: "primary") {
+                        arrays { name }
+                        tables { name }
+                    } } }
+                }}"#,
+            )
+            .await;
+        // Both fields share the stream's search but its invalid entry is only reported once
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("INVALID_TILED_RESPONSE"))
+        );
+        mock_stream.assert();
+    }
+
     #[tokio::test]
     async fn auth_forwarding() {
         let server = MockServer::start();
@@ -303,17 +843,661 @@ mod tests {
                 }));
             })
             .await;
+        let client = TiledClient::new(server.base_url().parse().unwrap());
         let schema = Schema::build(TiledQuery, EmptyMutation, EmptySubscription)
-            .data(TiledClient::new(server.base_url().parse().unwrap()))
+            .data(TiledLoader::data_loader(client.clone()))
+            .data(client)
             .data(Some(AuthHeader::from(HeaderValue::from_static(
                 "auth_value",
             ))))
             .finish();
         let response = schema
-            .execute(r#"{ instrumentSession(name: "cm12345-6"){ runs { id }}}"#)
+            .execute(r#"{ instrumentSession(name: "cm12345-6"){ runs { nodes { id } }}}"#)
             .await;
         assert_eq!(response.errors, &[]);
-        assert_eq!(response.data, value!({"instrumentSession": {"runs": []}}));
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": []}}})
+        );
         mock_instrument_session.assert();
     }
+
+    #[tokio::test]
+    async fn runs_pagination() {
+        let server = MockServer::start();
+        let mock_page = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param("page[offset]", "1")
+                    .query_param("page[limit]", "1");
+                then.status(200).json_body(json!({
+                    "data": [{"id": "not-a-run"}],
+                    "error": null,
+                    "links": {"self": "", "next": "next_page"},
+                    "meta": {"count": 3}
+                }));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs(first: 1, after: "0") {
+                        totalCount
+                        pageInfo { hasPreviousPage hasNextPage startCursor }
+                    }
+                }}"#,
+            )
+            .await;
+        // The only entry on the page is not a valid run
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .unwrap()
+                .get("nodeId"),
+            Some(&value!("not-a-run"))
+        );
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {
+                "totalCount": 3,
+                "pageInfo": {"hasPreviousPage": true, "hasNextPage": true, "startCursor": null}
+            }}})
+        );
+        mock_page.assert();
+    }
+
+    #[tokio::test]
+    async fn runs_select_metadata() {
+        let server = MockServer::start();
+        let mock_summary = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param("select_metadata", RUN_SUMMARY);
+                then.status(200).json_body(json!({
+                    "data": [{
+                        "id": "run_id",
+                        "attributes": {
+                            "ancestors": [],
+                            "structure_family": "container",
+                            "specs": [],
+                            "metadata": {
+                                "start": {
+                                    "uid": "5d8f5c3e-0e00-4c5c-816d-70b4b0f41498",
+                                    "time": 1761823745.0,
+                                    "instrument_session": "cm12345-6",
+                                    "scan_id": 49
+                                },
+                                "stop": null
+                            },
+                            "structure": {"contents": null, "count": 1},
+                            "access_blob": {},
+                            "sorting": null,
+                            "data_sources": null
+                        },
+                        "links": {"self": ""},
+                        "meta": null
+                    }],
+                    "error": null,
+                    "links": {"self": ""},
+                    "meta": {"count": 1}
+                }));
+            })
+            .await;
+        let mock_full = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param_missing("select_metadata");
+                then.status(200).json_body(json!({
+                    "data": [],
+                    "error": null,
+                    "links": {"self": ""},
+                    "meta": {"count": 0}
+                }));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { id scanNumber isComplete } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [
+                {"id": "run_id", "scanNumber": 49, "isComplete": false}
+            ]}}})
+        );
+        mock_summary.assert();
+
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { start { planName } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        mock_full.assert();
+    }
+
+    #[tokio::test]
+    async fn runs_sorting() {
+        let server = MockServer::start();
+        let mock_default = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/metadata/")
+                    .query_param("fields", "sorting");
+                then.status(200).json_body(json!({
+                    "data": {
+                        "id": "",
+                        "attributes": {
+                            "sorting": [{"key": "start.time", "direction": 1}],
+                        }
+                    }
+                }));
+            })
+            .await;
+        let mock_search = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/");
+                then.status(200).json_body(json!({
+                    "data": [],
+                    "error": null,
+                    "links": {"self": ""},
+                    "meta": {"count": 0}
+                }));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{
+                    default: instrumentSession(name: "cm12345-6") {
+                        runs { sorting { key direction } }
+                    }
+                    requested: instrumentSession(name: "cm12345-6") {
+                        runs(sort: [{field: SCAN_ID, direction: DESC}]) {
+                            sorting { key direction }
+                        }
+                    }
+                }"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({
+                "default": {"runs": {"sorting": [{"key": "start.time", "direction": 1}]}},
+                "requested": {"runs": {"sorting": [{"key": "start.scan_id", "direction": -1}]}}
+            })
+        );
+        // Only needed when no sort is requested
+        mock_default.assert();
+        mock_search.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn run_stream_sorting() {
+        let server = MockServer::start();
+        let [mock_session, ..] = mock_run_searches(&server).await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { streamSorting { key direction } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [
+                {"streamSorting": [{"key": "", "direction": 1}]}
+            ]}}})
+        );
+        mock_session.assert();
+    }
+
+    /// Mock the searches for a session containing one run with a single stream
+    async fn mock_run_searches(server: &MockServer) -> [Mock<'_>; 3] {
+        let mock_session = server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/");
+                then.status(200).json_body(json!({
+                    "data": [{
+                        "id": "run_id",
+                        "attributes": serde_json::from_str::<serde_json::Value>(
+                            &std::fs::read_to_string("resources/metadata_run.json").unwrap()
+                        ).unwrap()["data"]["attributes"],
+                        "links": {"self": ""},
+                        "meta": null
+                    }],
+                    "error": null,
+                    "links": {"self": ""},
+                    "meta": {"count": 1}
+                }));
+            })
+            .await;
+        let mock_run = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/run_id")
+                    .query_param_missing("include_data_sources");
+                then.status(200)
+                    .body_from_file("resources/search_run_container.json");
+            })
+            .await;
+        let mock_stream = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/run_id/primary")
+                    .query_param_missing("include_data_sources");
+                then.status(200)
+                    .body_from_file("resources/search_event_stream.json");
+            })
+            .await;
+        [mock_session, mock_run, mock_stream]
+    }
+
+    #[tokio::test]
+    async fn run_data() {
+        let server = MockServer::start();
+        let [mock_session, mock_run, mock_stream] = mock_run_searches(&server).await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { data {
+                        ... on ArrayData { name }
+                        ... on TableData { name }
+                    } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [
+                {"data": [{"name": "det"}, {"name": "internal"}]}
+            ]}}})
+        );
+        mock_session.assert();
+        mock_run.assert();
+        mock_stream.assert();
+    }
+
+    #[tokio::test]
+    async fn duplicate_requests_are_shared() {
+        let server = MockServer::start();
+        let [mock_session, mock_run, mock_stream] = mock_run_searches(&server).await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{
+                    a: instrumentSession(name: "cm12345-6") {
+                        runs { nodes { data { ... on ArrayData { name } } } }
+                    }
+                    b: instrumentSession(name: "cm12345-6") {
+                        runs { nodes { data { ... on TableData { name } } } }
+                    }
+                }"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        mock_session.assert();
+        mock_run.assert();
+        mock_stream.assert();
+    }
+
+    #[tokio::test]
+    async fn runs_count_only() {
+        let server = MockServer::start();
+        let mock_count = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/")
+                    .query_param("page[limit]", "0")
+                    .query_param("fields", "metadata")
+                    .query_param_missing("include_data_sources");
+                then.status(200).json_body(json!({
+                    "data": [],
+                    "error": null,
+                    "links": {"self": ""},
+                    "meta": {"count": 42}
+                }));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(r#"{ instrumentSession(name: "cm12345-6") { runs { totalCount } } }"#)
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"totalCount": 42}}})
+        );
+        mock_count.assert();
+    }
+
+    #[tokio::test]
+    async fn run_documents() {
+        let server = MockServer::start();
+        let [mock_session, ..] = mock_run_searches(&server).await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes {
+                        start { planName detectors }
+                        stop { exitStatus }
+                        startTime
+                        duration
+                        isComplete
+                    } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [{
+                "start": {"planName": "spec_scan", "detectors": ["det"]},
+                "stop": {"exitStatus": "success"},
+                "startTime": "2025-10-30T11:28:45.695689+00:00",
+                "duration": 22.61954140663147,
+                "isComplete": true
+            }]}}})
+        );
+        mock_session.assert();
+    }
+
+    #[tokio::test]
+    async fn run_streams() {
+        let server = MockServer::start();
+        let [_, mock_run, mock_stream] = mock_run_searches(&server).await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes {
+                        streams {
+                            name
+                            metadata { uid }
+                            arrays {
+                                name
+                                dataKey { dtype shape }
+                                structure { shape numFrames frameShape sizeBytes dataType { kind } }
+                            }
+                            tables { name stream dataKeys { name units } }
+                        }
+                        stream(name: "primary") { name }
+                        missing: stream(name: "baseline") { name }
+                    } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [{
+                "streams": [{
+                    "name": "primary",
+                    "metadata": {"uid": "97c7bb0c-8ebf-4d98-b506-abf20f382eb5"},
+                    "arrays": [{
+                        "name": "det",
+                        "dataKey": {"dtype": "array", "shape": [1, 1024, 1024]},
+                        "structure": {
+                            "shape": [5, 1024, 1024],
+                            "numFrames": 5,
+                            "frameShape": [1024, 1024],
+                            "sizeBytes": 5242880,
+                            "dataType": {"kind": "INT"}
+                        }
+                    }],
+                    "tables": [{
+                        "name": "internal",
+                        "stream": "primary",
+                        "dataKeys": [{"name": "stage-x", "units": "degrees"}]
+                    }]
+                }],
+                "stream": {"name": "primary"},
+                "missing": null
+            }]}}})
+        );
+        // Each search is only made once however many fields need it
+        mock_run.assert();
+        mock_stream.assert();
+    }
+
+    #[tokio::test]
+    async fn array_values() {
+        let server = MockServer::start();
+        mock_run_searches(&server).await;
+        let mock_values = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/array/full/4866611f-e6d9-4517-bedf-fc5526df57ad/primary/det")
+                    .query_param("slice", "0,0,0:4")
+                    .header("accept", "application/json");
+                then.status(200).json_body(json!([1, 2, 3, 4]));
+            })
+            .await;
+        let schema = build_schema_with_client(
+            TiledClient::for_mock_server(&server).with_max_array_elements(100),
+        );
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { stream(name: "primary") { arrays {
+                        values(slice: "0,0,0:4")
+                    } } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [{
+                "stream": {"arrays": [{"values": [1, 2, 3, 4]}]}
+            }]}}})
+        );
+        mock_values.assert();
+    }
+
+    #[tokio::test]
+    async fn array_values_too_large() {
+        let server = MockServer::start();
+        mock_run_searches(&server).await;
+        let mock_values = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/array/full/4866611f-e6d9-4517-bedf-fc5526df57ad/primary/det");
+                then.status(200).json_body(json!([]));
+            })
+            .await;
+        let schema = build_schema_with_client(
+            TiledClient::for_mock_server(&server).with_max_array_elements(100),
+        );
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { stream(name: "primary") { arrays {
+                        all: values
+                        frame: values(slice: "0")
+                        invalid: values(slice: "0,a")
+                    } } } }
+                }}"#,
+            )
+            .await;
+        let mut errors = response
+            .errors
+            .iter()
+            .map(|e| {
+                let PathSegment::Field(field) = &e.path[7] else {
+                    panic!("Unexpected error path: {:?}", e.path);
+                };
+                let code = e.extensions.as_ref().unwrap().get("code").unwrap();
+                (field.clone(), code.clone())
+            })
+            .collect::<Vec<_>>();
+        errors.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            errors,
+            [
+                ("all".into(), value!("ARRAY_TOO_LARGE")),
+                ("frame".into(), value!("ARRAY_TOO_LARGE")),
+                ("invalid".into(), value!("INVALID_SLICE")),
+            ]
+        );
+        mock_values.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn table_partition() {
+        let server = MockServer::start();
+        mock_run_searches(&server).await;
+        let mock_partition = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/table/partition/4866611f-e6d9-4517-bedf-fc5526df57ad/primary/internal")
+                    .query_param("partition", "0")
+                    .query_param("column", "stage-x");
+                then.status(200)
+                    .json_body(json!({"stage-x": [0, 2.5, 5, 7.5, 10]}));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { stream(name: "primary") { tables {
+                        npartitions
+                        columnInfo { name kind }
+                        data(columns: ["stage-x"], partition: 0, offset: 1, limit: 2)
+                        typedData(columns: ["stage-x"], partition: 0, limit: 2) {
+                            ... on FloatColumn { name values }
+                        }
+                        missing: data(partition: 1)
+                    } } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("INVALID_PARTITION"))
+        );
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [{
+                "stream": {"tables": [{
+                    "npartitions": 1,
+                    "columnInfo": [
+                        {"name": "seq_num", "kind": "INT"},
+                        {"name": "time", "kind": "FLOAT"},
+                        {"name": "stage-x", "kind": "FLOAT"},
+                        {"name": "ts_stage-x", "kind": "FLOAT"},
+                    ],
+                    "data": {"stage-x": [2.5, 5]},
+                    "typedData": [{"name": "stage-x", "values": [0.0, 2.5]}],
+                    "missing": null
+                }]}
+            }]}}})
+        );
+        mock_partition.assert();
+    }
+
+    #[tokio::test]
+    async fn table_rows_need_partition() {
+        let server = MockServer::start();
+        let [_, _, mock_stream] = mock_run_searches(&server).await;
+        mock_stream.delete_async().await;
+        let mut stream: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/search_event_stream.json").unwrap(),
+        )
+        .unwrap();
+        stream["data"][1]["attributes"]["structure"]["npartitions"] = json!(3);
+        server
+            .mock_async(|when, then| {
+                when.method("GET").path("/api/v1/search/run_id/primary");
+                then.status(200).json_body(stream);
+            })
+            .await;
+        let mock_table = server
+            .mock_async(|when, then| {
+                when.method("GET").path_includes("/api/v1/table/");
+                then.status(200).json_body(json!({"stage-x": [0, 2.5]}));
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let response = schema
+            .execute(
+                r#"{ instrumentSession(name: "cm12345-6") {
+                    runs { nodes { stream(name: "primary") { tables {
+                        data(columns: ["stage-x"], limit: 2)
+                    } } } }
+                }}"#,
+            )
+            .await;
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0].extensions.as_ref().unwrap().get("code"),
+            Some(&value!("PARTITION_REQUIRED"))
+        );
+        mock_table.assert_calls(0);
+    }
+
+    #[tokio::test]
+    async fn asset_download_links() {
+        let server = MockServer::start();
+        let [_, mock_run, _] = mock_run_searches(&server).await;
+        let mock_stream = server
+            .mock_async(|when, then| {
+                when.method("GET")
+                    .path("/api/v1/search/run_id/primary")
+                    .query_param("include_data_sources", "true");
+                then.status(200)
+                    .body_from_file("resources/search_event_stream.json");
+            })
+            .await;
+        let schema = build_schema(&server.base_url());
+        let links = LinkBase::new(
+            Some("https://example.com/glazed/".parse().unwrap()),
+            "0.0.0.0:3000".parse().unwrap(),
+        );
+        let response = schema
+            .execute(
+                Request::new(
+                    r#"{ instrumentSession(name: "cm12345-6") {
+                        runs { nodes { stream(name: "primary") { arrays {
+                            files { file download }
+                        } } } }
+                    }}"#,
+                )
+                .data(links.resolve(&HeaderMap::new())),
+            )
+            .await;
+        assert_eq!(response.errors, &[]);
+        assert_eq!(
+            response.data,
+            value!({"instrumentSession": {"runs": {"nodes": [{
+                "stream": {"arrays": [{"files": [{
+                    "file": "file://localhost/home/abi/data/adsim-2-det.h5",
+                    "download": "https://example.com/glazed/asset/run_id/primary/det/18"
+                }]}]}
+            }]}}})
+        );
+        mock_run.assert();
+        mock_stream.assert();
+    }
 }
@@ -1,20 +1,249 @@
-use async_graphql::SimpleObject;
+use async_graphql::{ComplexObject, Enum, SimpleObject};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct ArrayStructure {
-    data_type: DataType,
-    chunks: Value,
-    shape: Value,
-    dims: Value,
+    pub data_type: DataType,
+    /// The size of each chunk along each dimension
+    pub chunks: Vec<Vec<u64>>,
+    pub shape: Vec<u64>,
+    /// Names of each dimension, if set
+    pub dims: Option<Vec<String>>,
     resizable: bool,
 }
 
+#[ComplexObject]
+impl ArrayStructure {
+    /// The number of frames (the size of the first dimension). For event stream data this is
+    /// the number of events.
+    #[graphql(name = "numFrames")]
+    async fn resolve_num_frames(&self) -> u64 {
+        self.num_frames()
+    }
+    /// The shape of each frame (every dimension after the first)
+    #[graphql(name = "frameShape")]
+    async fn resolve_frame_shape(&self) -> &[u64] {
+        self.frame_shape()
+    }
+    /// The total size of the array data in bytes
+    #[graphql(name = "sizeBytes")]
+    async fn resolve_size_bytes(&self) -> u64 {
+        self.size_bytes()
+    }
+}
+
+impl ArrayStructure {
+    pub fn num_frames(&self) -> u64 {
+        self.shape.first().copied().unwrap_or(1)
+    }
+    pub fn frame_shape(&self) -> &[u64] {
+        self.shape.get(1..).unwrap_or_default()
+    }
+    pub fn num_elements(&self) -> u64 {
+        self.shape.iter().product()
+    }
+    pub fn size_bytes(&self) -> u64 {
+        self.num_elements() * self.data_type.itemsize
+    }
+    /// The number of elements selected by a numpy style slice, eg `0,::2,100:200`. Dimensions
+    /// not included in the slice are selected in full.
+    pub fn sliced_elements(&self, slice: &str) -> Result<u64, String> {
+        let slice = slice.trim();
+        let dims = if slice.is_empty() {
+            vec![]
+        } else {
+            slice.split(',').map(str::trim).collect::<Vec<_>>()
+        };
+        if dims.len() > self.shape.len() {
+            return Err(format!(
+                "Slice has {} dimensions but array only has {}",
+                dims.len(),
+                self.shape.len()
+            ));
+        }
+        self.shape
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| match dims.get(i) {
+                Some(dim) => dim_elements(dim, len),
+                None => Ok(len),
+            })
+            .product()
+    }
+}
+
+/// The number of elements selected from a dimension of length `len` by a single index or range
+fn dim_elements(dim: &str, len: u64) -> Result<u64, String> {
+    let len = len as i64;
+    let parse = |value: &str| -> Result<Option<i64>, String> {
+        match value.trim() {
+            "" => Ok(None),
+            v => v
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid slice index: {v:?}")),
+        }
+    };
+    let parts = dim.split(':').collect::<Vec<_>>();
+    match parts[..] {
+        [index] => {
+            let index = parse(index)?.ok_or("Empty slice index")?;
+            if (-len..len).contains(&index) {
+                Ok(1)
+            } else {
+                Err(format!(
+                    "Index {index} out of range for dimension of length {len}"
+                ))
+            }
+        }
+        [start, stop] => Ok(range_elements(parse(start)?, parse(stop)?, 1, len)),
+        [start, stop, step] => {
+            let step = parse(step)?.unwrap_or(1);
+            if step == 0 {
+                return Err("Slice step cannot be zero".into());
+            }
+            Ok(range_elements(parse(start)?, parse(stop)?, step, len))
+        }
+        _ => Err(format!("Invalid slice: {dim:?}")),
+    }
+}
+
+/// The length of a range following python's slicing rules
+fn range_elements(start: Option<i64>, stop: Option<i64>, step: i64, len: i64) -> u64 {
+    // Resolve negative indices and clamp to the valid range for the direction of the step
+    let clamp = |index: i64, low: i64, high: i64| {
+        let index = if index < 0 { index + len } else { index };
+        index.clamp(low, high)
+    };
+    let count = if step > 0 {
+        let start = start.map_or(0, |s| clamp(s, 0, len));
+        let stop = stop.map_or(len, |s| clamp(s, 0, len));
+        (stop - start + step - 1) / step
+    } else {
+        let start = start.map_or(len - 1, |s| clamp(s, -1, len - 1));
+        let stop = stop.map_or(-1, |s| clamp(s, -1, len - 1));
+        (start - stop - step - 1) / -step
+    };
+    count.max(0) as u64
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct DataType {
-    endianness: String,
-    kind: String,
-    itemsize: i64,
-    dt_units: Value,
+    pub endianness: Endianness,
+    pub kind: Kind,
+    /// The size of each element in bytes
+    pub itemsize: u64,
+    /// Units of datetime and timedelta types, eg `s` or `ns`
+    pub dt_units: Option<String>,
+}
+
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endianness {
+    Big,
+    Little,
+    NotApplicable,
+}
+
+/// The kind of each element, matching numpy's `dtype.kind`
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    #[serde(rename = "t")]
+    BitField,
+    #[serde(rename = "b")]
+    Bool,
+    #[serde(rename = "i")]
+    Int,
+    #[serde(rename = "u")]
+    Uint,
+    #[serde(rename = "f")]
+    Float,
+    #[serde(rename = "c")]
+    Complex,
+    #[serde(rename = "m")]
+    Timedelta,
+    #[serde(rename = "M")]
+    Datetime,
+    #[serde(rename = "S")]
+    Bytes,
+    #[serde(rename = "U")]
+    Unicode,
+    #[serde(rename = "V")]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{ArrayStructure, Endianness, Kind};
+
+    #[test]
+    fn typed_array_structure() {
+        let array: Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/metadata_array.json").unwrap(),
+        )
+        .unwrap();
+        let structure: ArrayStructure =
+            serde_json::from_value(array["data"]["attributes"]["structure"].clone()).unwrap();
+
+        assert_eq!(structure.shape, [5, 1024, 1024]);
+        assert_eq!(structure.chunks, [vec![1; 5], vec![1024], vec![1024]]);
+        assert_eq!(structure.dims, None);
+        assert_eq!(structure.data_type.kind, Kind::Int);
+        assert_eq!(structure.data_type.endianness, Endianness::NotApplicable);
+        assert_eq!(structure.num_frames(), 5);
+        assert_eq!(structure.frame_shape(), [1024, 1024]);
+        assert_eq!(structure.size_bytes(), 5 * 1024 * 1024);
+    }
+
+    #[test]
+    fn sliced_elements() {
+        let structure: ArrayStructure = serde_json::from_value(serde_json::json!({
+            "data_type": {"endianness": "little", "kind": "f", "itemsize": 8, "dt_units": null},
+            "chunks": [[5], [10], [20]],
+            "shape": [5, 10, 20],
+            "dims": null,
+            "resizable": false
+        }))
+        .unwrap();
+        let elements = |slice| structure.sliced_elements(slice);
+        assert_eq!(elements(""), Ok(1000));
+        assert_eq!(elements("0"), Ok(200));
+        assert_eq!(elements("-1,::2"), Ok(100));
+        assert_eq!(elements("0,::2,5:15"), Ok(50));
+        assert_eq!(elements("1:3, 2, :"), Ok(40));
+        assert_eq!(elements("::-1,-3:,100:"), Ok(0));
+        assert_eq!(elements("::-2,-3:"), Ok(3 * 3 * 20));
+        assert_eq!(elements("4:1:-1"), Ok(3 * 10 * 20));
+        assert!(elements("5").is_err());
+        assert!(elements("::0").is_err());
+        assert!(elements("a").is_err());
+        assert!(elements("0,0,0,0").is_err());
+    }
+
+    #[test]
+    fn scalar_array_structure() {
+        let structure: ArrayStructure = serde_json::from_value(serde_json::json!({
+            "data_type": {"endianness": "little", "kind": "f", "itemsize": 8, "dt_units": null},
+            "chunks": [],
+            "shape": [],
+            "dims": null,
+            "resizable": false
+        }))
+        .unwrap();
+        assert_eq!(structure.num_frames(), 1);
+        assert!(structure.frame_shape().is_empty());
+        assert_eq!(structure.size_bytes(), 8);
+    }
+
+    #[test]
+    fn numpy_kinds() {
+        let kinds: Vec<Kind> =
+            serde_json::from_str(r#"["t", "b", "i", "u", "f", "c", "m", "M", "S", "U", "V"]"#)
+                .unwrap();
+        assert_eq!(kinds[0], Kind::BitField);
+        assert_eq!(kinds[10], Kind::Other);
+    }
 }
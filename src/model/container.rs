@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::model::event_stream;
-use crate::model::run::{self, Start};
+use crate::model::run::{self, Start, Stop};
 
 #[derive(Union, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase", untagged)]
@@ -20,6 +20,27 @@ impl ContainerMetadata {
             None
         }
     }
+    pub fn event_stream(&self) -> Option<&event_stream::EventStreamMetadata> {
+        if let ContainerMetadata::EventStream(stream) = self {
+            Some(stream)
+        } else {
+            None
+        }
+    }
+    pub fn raw(&self) -> Option<&Value> {
+        if let ContainerMetadata::Run(run) = self {
+            Some(&run.raw)
+        } else {
+            None
+        }
+    }
+    pub fn stop_doc(&self) -> Option<&Stop> {
+        if let ContainerMetadata::Run(run) = self {
+            run.stop.as_ref()
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
@@ -1,26 +1,142 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use async_graphql::SimpleObject;
+use async_graphql::{ComplexObject, SimpleObject};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+#[graphql(complex)]
 pub struct EventStreamMetadata {
     configuration: HashMap<String, HashMap<String, Value>>,
-    data_keys: HashMap<String, HashMap<String, Value>>,
+    #[graphql(skip)]
+    data_keys: BTreeMap<String, DataKey>,
     time: f64,
     uid: Uuid,
     hints: HashMap<String, Value>,
 }
 
+#[ComplexObject]
+impl EventStreamMetadata {
+    /// Descriptions of each field recorded in this stream
+    async fn data_keys(&self) -> Vec<DataKey> {
+        self.data_keys
+            .keys()
+            .filter_map(|name| self.data_key(name))
+            .collect()
+    }
+}
+
+impl EventStreamMetadata {
+    /// The description of the named field, if it is recorded in this stream
+    pub fn data_key(&self, name: &str) -> Option<DataKey> {
+        self.data_keys.get(name).map(|key| DataKey {
+            name: name.to_owned(),
+            ..key.clone()
+        })
+    }
+}
+
+/// Description of a single field recorded by an event stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct DataKey {
+    /// The name of the field. This is the key the description is stored under in the stream's
+    /// metadata.
+    #[serde(skip)]
+    pub name: String,
+    /// The JSON type of the data, eg number, string or array
+    pub dtype: Option<String>,
+    /// The numpy type string of the data, eg `<f8`
+    pub dtype_numpy: Option<String>,
+    /// The shape of each value. Dimensions of variable size are null.
+    pub shape: Option<Vec<Option<i64>>>,
+    pub units: Option<String>,
+    pub precision: Option<i64>,
+    /// Where the data came from, eg a PV
+    pub source: Option<String>,
+    pub limits: Option<Limits>,
+    /// Set if the data is stored externally, eg in a detector's HDF5 file
+    pub external: Option<String>,
+    /// The name of the device the data came from
+    pub object_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct Limits {
+    pub control: Option<LimitRange>,
+    pub display: Option<LimitRange>,
+    pub warning: Option<LimitRange>,
+    pub alarm: Option<LimitRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+pub struct LimitRange {
+    pub low: Option<f64>,
+    pub high: Option<f64>,
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::model::node;
+    use crate::model::node::{self, NodeAttributes};
     use crate::test_utils::assert_readable_as;
 
     #[test]
     fn search_run_container_for_event_stream_containers() {
         assert_readable_as::<node::Root>("resources/search_run_container.json");
     }
+
+    #[test]
+    fn typed_data_keys() {
+        let root: node::Root = serde_json::from_str(
+            &std::fs::read_to_string("resources/search_run_container.json").unwrap(),
+        )
+        .unwrap();
+        let stream = root.into_data().next().unwrap();
+        let NodeAttributes::Container(attrs) = *stream.attributes else {
+            panic!("Stream was not a container");
+        };
+        let metadata = attrs.metadata.event_stream().unwrap();
+
+        let det = metadata.data_key("det").unwrap();
+        assert_eq!(det.name, "det");
+        assert_eq!(
+            det.shape.as_deref(),
+            Some(&[Some(1), Some(1024), Some(1024)][..])
+        );
+        assert_eq!(det.external.as_deref(), Some("STREAM:"));
+
+        let motor = metadata.data_key("stage-x").unwrap();
+        assert_eq!(motor.units.as_deref(), Some("degrees"));
+        assert_eq!(motor.precision, Some(5));
+        assert_eq!(
+            motor.limits.and_then(|l| l.control).and_then(|c| c.high),
+            Some(20000.0)
+        );
+        assert!(metadata.data_key("time").is_none());
+    }
+
+    #[test]
+    fn partial_data_key() {
+        let mut root: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/search_run_container.json").unwrap(),
+        )
+        .unwrap();
+        let det = &mut root["data"][0]["attributes"]["metadata"]["data_keys"]["det"];
+        let det = det.as_object_mut().unwrap();
+        det.remove("dtype");
+        det.remove("shape");
+
+        let root: node::Root = serde_json::from_value(root).unwrap();
+        assert_eq!(root.invalid().count(), 0);
+        let stream = root.into_data().next().unwrap();
+        let NodeAttributes::Container(attrs) = *stream.attributes else {
+            panic!("Stream was not a container");
+        };
+        let metadata = attrs.metadata.event_stream().unwrap();
+        let det = metadata.data_key("det").unwrap();
+        assert_eq!(det.dtype, None);
+        assert_eq!(det.shape, None);
+        assert_eq!(det.external.as_deref(), Some("STREAM:"));
+        assert!(metadata.data_key("stage-x").unwrap().dtype.is_some());
+    }
 }
@@ -0,0 +1,133 @@
+use std::borrow::Cow;
+
+use async_graphql::{Enum, InputObject};
+use serde_json::{Value, json};
+
+/// Restrictions on the runs returned by a search. Each field is translated into a tiled
+/// `filter[...]` query so that filtering is done by tiled rather than after fetching.
+#[derive(InputObject, Debug, Default, Clone)]
+pub struct RunFilter {
+    /// Only include runs with a scan number greater than or equal to this
+    pub scan_number_min: Option<i64>,
+    /// Only include runs with a scan number less than or equal to this
+    pub scan_number_max: Option<i64>,
+    /// Only include runs started at or after this time (seconds since the epoch)
+    pub start_time_from: Option<f64>,
+    /// Only include runs started at or before this time (seconds since the epoch)
+    pub start_time_to: Option<f64>,
+    pub plan_name: Option<String>,
+    pub instrument: Option<String>,
+    /// Only include runs that used the named detector
+    pub detector: Option<String>,
+    /// Only include completed runs with the given exit status
+    pub exit_status: Option<ExitStatus>,
+}
+
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ExitStatus {
+    Success,
+    Abort,
+    Fail,
+}
+
+impl ExitStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ExitStatus::Success => "success",
+            ExitStatus::Abort => "abort",
+            ExitStatus::Fail => "fail",
+        }
+    }
+}
+
+impl RunFilter {
+    /// The tiled query parameters needed to apply this filter
+    pub fn query_params(&self) -> Vec<(&'static str, Cow<'static, str>)> {
+        let mut params = Vec::new();
+        let comparisons = [
+            ("start.scan_id", "ge", self.scan_number_min.map(Value::from)),
+            ("start.scan_id", "le", self.scan_number_max.map(Value::from)),
+            ("start.time", "ge", self.start_time_from.map(Value::from)),
+            ("start.time", "le", self.start_time_to.map(Value::from)),
+        ];
+        for (key, operator, value) in comparisons {
+            if let Some(value) = value {
+                params.push(("filter[comparison][condition][operator]", operator.into()));
+                params.push(("filter[comparison][condition][key]", key.into()));
+                params.push((
+                    "filter[comparison][condition][value]",
+                    value.to_string().into(),
+                ));
+            }
+        }
+        let equalities = [
+            ("start.plan_name", self.plan_name.as_deref()),
+            ("start.instrument", self.instrument.as_deref()),
+            (
+                "stop.exit_status",
+                self.exit_status.as_ref().map(ExitStatus::as_str),
+            ),
+        ];
+        for (key, value) in equalities {
+            if let Some(value) = value {
+                params.push(("filter[eq][condition][key]", key.into()));
+                params.push((
+                    "filter[eq][condition][value]",
+                    json!(value).to_string().into(),
+                ));
+            }
+        }
+        if let Some(detector) = &self.detector {
+            params.push(("filter[contains][condition][key]", "start.detectors".into()));
+            params.push((
+                "filter[contains][condition][value]",
+                json!(detector).to_string().into(),
+            ));
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ExitStatus, RunFilter};
+
+    #[test]
+    fn empty_filter() {
+        assert!(RunFilter::default().query_params().is_empty());
+    }
+
+    #[test]
+    fn filter_params() {
+        let filter = RunFilter {
+            scan_number_min: Some(100),
+            scan_number_max: Some(200),
+            plan_name: Some("grid_scan".into()),
+            detector: Some("det".into()),
+            exit_status: Some(ExitStatus::Success),
+            ..Default::default()
+        };
+        let params = filter
+            .query_params()
+            .into_iter()
+            .map(|(k, v)| (k, v.into_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            params,
+            [
+                ("filter[comparison][condition][operator]", "ge".into()),
+                ("filter[comparison][condition][key]", "start.scan_id".into()),
+                ("filter[comparison][condition][value]", "100".into()),
+                ("filter[comparison][condition][operator]", "le".into()),
+                ("filter[comparison][condition][key]", "start.scan_id".into()),
+                ("filter[comparison][condition][value]", "200".into()),
+                ("filter[eq][condition][key]", "start.plan_name".into()),
+                ("filter[eq][condition][value]", r#""grid_scan""#.into()),
+                ("filter[eq][condition][key]", "stop.exit_status".into()),
+                ("filter[eq][condition][value]", r#""success""#.into()),
+                ("filter[contains][condition][key]", "start.detectors".into()),
+                ("filter[contains][condition][value]", r#""det""#.into()),
+            ]
+        );
+    }
+}
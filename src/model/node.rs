@@ -1,12 +1,12 @@
 use std::collections::HashMap;
 
-use async_graphql::{Enum, SimpleObject};
-use serde::{Deserialize, Serialize};
+use async_graphql::{Enum, ErrorExtensions, SimpleObject};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 use crate::model::{array, container, table};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Root {
     data: Vec<DataOption>,
     pub error: Value,
@@ -21,13 +21,85 @@ impl Root {
     pub fn into_data(self) -> impl Iterator<Item = Data> {
         self.data.into_iter().flat_map(DataOption::into_data)
     }
+    /// The entries that could not be parsed and are skipped by `data` and `into_data`
+    pub fn invalid(&self) -> impl Iterator<Item = &InvalidEntry> {
+        self.data.iter().flat_map(DataOption::as_invalid)
+    }
+    /// Each parsed entry along with its position in this page of results
+    pub fn into_indexed_data(self) -> impl Iterator<Item = (usize, Data)> {
+        self.data
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, d)| d.into_data().map(|d| (i, d)))
+    }
+    /// The total number of results matching the search, if reported by tiled
+    pub fn count(&self) -> Option<usize> {
+        self.meta
+            .get("count")
+            .and_then(Value::as_u64)
+            .map(|c| c as usize)
+    }
+    /// The number of entries in this page of results, including those that could not be parsed
+    pub fn page_len(&self) -> usize {
+        self.data.len()
+    }
+    /// Whether tiled has more results available after this page
+    pub fn has_next(&self) -> bool {
+        self.links.as_ref().is_some_and(|l| l.next.is_some())
+    }
+    /// Append the entries from a subsequent page of results, taking its links so that the
+    /// combined result reflects the last page fetched.
+    pub fn extend(&mut self, page: Root) {
+        self.data.extend(page.data);
+        self.links = page.links;
+    }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum DataOption {
     Data(Data),
-    Error(Value),
+    Error(InvalidEntry),
+}
+
+impl<'de> Deserialize<'de> for DataOption {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Ok(match Data::deserialize(&value) {
+            Ok(data) => Self::Data(data),
+            Err(err) => Self::Error(InvalidEntry {
+                id: value.get("id").and_then(Value::as_str).map(String::from),
+                error: err.to_string(),
+                raw: value,
+            }),
+        })
+    }
+}
+
+/// An entry in a set of search results that could not be parsed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InvalidEntry {
+    pub id: Option<String>,
+    pub error: String,
+    pub raw: Value,
+}
+
+impl ErrorExtensions for InvalidEntry {
+    fn extend(&self) -> async_graphql::Error {
+        let message = match &self.id {
+            Some(id) => format!("Invalid tiled node '{id}': {}", self.error),
+            None => format!("Invalid tiled node: {}", self.error),
+        };
+        async_graphql::Error::new(message).extend_with(|_, ext| {
+            ext.set("code", "INVALID_TILED_RESPONSE");
+            if let Some(id) = &self.id {
+                ext.set("nodeId", id.as_str());
+            }
+        })
+    }
 }
 
 impl DataOption {
@@ -43,6 +115,41 @@ impl DataOption {
             Self::Error(_) => None,
         }
     }
+    pub fn as_invalid(&self) -> Option<&InvalidEntry> {
+        match self {
+            Self::Data(_) => None,
+            Self::Error(entry) => Some(entry),
+        }
+    }
+}
+
+/// The response to a request for a single node's metadata
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub data: Data,
+}
+
+/// The response to a request for only the sorting of a node, as used for containers whose
+/// metadata is not otherwise modelled (eg the root of the catalog)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SortingMetadata {
+    data: SortingData,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct SortingData {
+    attributes: SortingAttributes,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct SortingAttributes {
+    sorting: Option<Vec<Sorting>>,
+}
+
+impl SortingMetadata {
+    pub fn into_sorting(self) -> Vec<Sorting> {
+        self.data.attributes.sorting.unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -61,6 +168,16 @@ pub enum NodeAttributes {
     Table(Attributes<HashMap<String, Value>, table::TableStructure>),
 }
 
+impl NodeAttributes {
+    pub fn sorting(&self) -> Option<&[Sorting]> {
+        match self {
+            NodeAttributes::Container(attrs) => attrs.sorting.as_deref(),
+            NodeAttributes::Array(attrs) => attrs.sorting.as_deref(),
+            NodeAttributes::Table(attrs) => attrs.sorting.as_deref(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Attributes<Meta, S> {
     pub ancestors: Vec<String>,
@@ -78,9 +195,10 @@ pub struct Spec {
     pub version: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct Sorting {
     pub key: String,
+    /// 1 for ascending, -1 for descending
     pub direction: i64,
 }
 
@@ -106,7 +224,7 @@ pub enum Management {
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct Asset {
     pub data_uri: String,
-    is_directory: bool,
+    pub is_directory: bool,
     parameter: Option<String>,
     num: Option<i64>,
     pub id: Option<i64>,
@@ -5,43 +5,78 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
+/// The documents describing a run. Only the fields needed to identify a run are required, so
+/// that runs from older versions of bluesky or from other facilities' plans can still be read.
+/// The documents are also kept as received so that fields not modelled here are not lost.
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
 pub struct RunMetadata {
     pub start: Start,
     pub stop: Option<Stop>,
+    /// The metadata exactly as received from tiled
+    #[graphql(skip)]
+    pub raw: Value,
+}
+
+impl<'de> Deserialize<'de> for RunMetadata {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Documents {
+            start: Start,
+            stop: Option<Stop>,
+        }
+        let raw = Value::deserialize(deserializer)?;
+        let docs = Documents::deserialize(&raw).map_err(serde::de::Error::custom)?;
+        Ok(Self {
+            start: docs.start,
+            stop: docs.stop,
+            raw,
+        })
+    }
+}
+
+impl Serialize for RunMetadata {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.raw.serialize(serializer)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct Start {
     pub uid: Uuid,
     pub time: f64,
-    pub versions: Versions,
-    pub instrument: String,
+    pub versions: Option<Versions>,
+    pub instrument: Option<String>,
     pub instrument_session: String,
     pub data_session_directory: Option<String>,
     pub scan_file: Option<String>,
     pub scan_id: i64,
-    pub plan_type: String,
-    pub plan_name: String,
-    pub detectors: Vec<String>,
+    pub plan_type: Option<String>,
+    pub plan_name: Option<String>,
+    pub detectors: Option<Vec<String>>,
     pub motors: Option<Vec<String>>,
-    pub num_points: i64,
-    pub num_intervals: i64,
-    pub plan_args: HashMap<String, Value>,
-    pub hints: Hints,
-    pub shape: Vec<i64>,
+    pub num_points: Option<i64>,
+    pub num_intervals: Option<i64>,
+    pub plan_args: Option<HashMap<String, Value>>,
+    pub hints: Option<Hints>,
+    pub shape: Option<Vec<i64>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct Versions {
-    pub ophyd: String,
-    pub ophyd_async: String,
-    pub bluesky: String,
+    pub ophyd: Option<String>,
+    pub ophyd_async: Option<String>,
+    pub bluesky: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, SimpleObject)]
 pub struct Hints {
-    pub dimensions: Vec<HintDimension>,
+    pub dimensions: Option<Vec<HintDimension>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, SimpleObject)]
@@ -66,17 +101,44 @@ pub struct Stop {
     pub time: f64,
     pub run_start: Uuid,
     pub exit_status: String,
-    pub reason: String,
-    pub num_events: HashMap<String, Value>,
+    pub reason: Option<String>,
+    pub num_events: Option<HashMap<String, Value>>,
 }
 
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+
     use crate::model::node;
+    use crate::model::run::RunMetadata;
     use crate::test_utils::assert_readable_as;
 
     #[test]
     fn search_root_for_run_containers() {
         assert_readable_as::<node::Root>("resources/search_root.json");
     }
+
+    #[test]
+    fn minimal_run_metadata() {
+        let raw = json!({
+            "start": {
+                "uid": "4866611f-e6d9-4517-bedf-fc5526df57ad",
+                "time": 1762787606.07,
+                "instrument_session": "cm12345-2",
+                "scan_id": 2,
+                "sample": "unknown to glazed"
+            },
+            "stop": {
+                "uid": "def4aa81-db42-497d-92e9-1d519d252c7e",
+                "time": 1762787618.46,
+                "run_start": "4866611f-e6d9-4517-bedf-fc5526df57ad",
+                "exit_status": "success"
+            }
+        });
+        let metadata = serde_json::from_value::<RunMetadata>(raw.clone()).unwrap();
+        assert_eq!(metadata.start.scan_id, 2);
+        assert_eq!(metadata.start.plan_name, None);
+        assert_eq!(metadata.raw, raw);
+        assert_eq!(serde_json::to_value(&metadata).unwrap(), raw);
+    }
 }
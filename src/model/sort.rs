@@ -0,0 +1,78 @@
+use async_graphql::{Enum, InputObject};
+
+use crate::model::node::Sorting;
+
+/// A key to order runs by. Sorts are applied by tiled in the order given.
+#[derive(InputObject, Debug, Copy, Clone)]
+pub struct RunSort {
+    pub field: RunSortField,
+    #[graphql(default)]
+    pub direction: SortDirection,
+}
+
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RunSortField {
+    ScanId,
+    StartTime,
+    PlanName,
+}
+
+#[derive(Enum, Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl RunSort {
+    /// The metadata key that tiled should sort by
+    fn key(&self) -> &'static str {
+        match self.field {
+            RunSortField::ScanId => "start.scan_id",
+            RunSortField::StartTime => "start.time",
+            RunSortField::PlanName => "start.plan_name",
+        }
+    }
+
+    pub fn as_sorting(&self) -> Sorting {
+        Sorting {
+            key: self.key().into(),
+            direction: match self.direction {
+                SortDirection::Asc => 1,
+                SortDirection::Desc => -1,
+            },
+        }
+    }
+
+    /// Build the value of tiled's `sort` query parameter from a list of sort keys
+    pub fn query_param(sorts: &[RunSort]) -> String {
+        sorts
+            .iter()
+            .map(|s| match s.direction {
+                SortDirection::Asc => s.key().to_owned(),
+                SortDirection::Desc => format!("-{}", s.key()),
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunSort, RunSortField, SortDirection};
+
+    #[test]
+    fn sort_param() {
+        let sorts = [
+            RunSort {
+                field: RunSortField::StartTime,
+                direction: SortDirection::Desc,
+            },
+            RunSort {
+                field: RunSortField::ScanId,
+                direction: SortDirection::Asc,
+            },
+        ];
+        assert_eq!(RunSort::query_param(&sorts), "-start.time,start.scan_id");
+    }
+}
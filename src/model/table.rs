@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use async_graphql::SimpleObject;
+use arrow_schema::{DataType, Schema};
+use async_graphql::{Enum, SimpleObject, Union};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
@@ -13,3 +16,189 @@ pub struct TableStructure {
     pub columns: Vec<String>,
     pub resizable: bool,
 }
+
+impl TableStructure {
+    /// Decode the arrow schema. Tiled sends it as a base64 encoded data URI.
+    pub fn schema(&self) -> Result<Schema, String> {
+        let (_, encoded) = self
+            .arrow_schema
+            .split_once(";base64,")
+            .ok_or("Arrow schema is not a base64 data URI")?;
+        let bytes = STANDARD
+            .decode(encoded)
+            .map_err(|e| format!("Arrow schema is not valid base64: {e}"))?;
+        arrow_ipc::convert::try_schema_from_ipc_buffer(&bytes)
+            .map_err(|e| format!("Invalid arrow schema: {e}"))
+    }
+    /// The name and type of each column
+    pub fn column_info(&self) -> Result<Vec<Column>, String> {
+        Ok(self
+            .schema()?
+            .fields()
+            .iter()
+            .map(|field| Column {
+                name: field.name().clone(),
+                data_type: field.data_type().to_string(),
+                kind: ColumnKind::of(field.data_type()),
+                nullable: field.is_nullable(),
+            })
+            .collect())
+    }
+}
+
+/// Description of a single column of a table
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+pub struct Column {
+    pub name: String,
+    /// The arrow type of the column, eg `Float64` or `Timestamp(ns)`
+    pub data_type: String,
+    /// The type the column's values are returned as
+    pub kind: ColumnKind,
+    pub nullable: bool,
+}
+
+#[derive(Enum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ColumnKind {
+    Float,
+    Int,
+    String,
+    Boolean,
+}
+
+impl ColumnKind {
+    /// The closest GraphQL type to an arrow type. Anything that isn't a number or boolean, such
+    /// as timestamps, is returned as a string.
+    pub fn of(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Boolean => Self::Boolean,
+            dt if dt.is_integer() => Self::Int,
+            dt if dt.is_floating() || dt.is_numeric() => Self::Float,
+            _ => Self::String,
+        }
+    }
+}
+
+/// The values of a column, typed according to the table's schema
+#[derive(Debug, Clone, PartialEq, Union)]
+pub enum ColumnValues {
+    Float(FloatColumn),
+    Int(IntColumn),
+    String(StringColumn),
+    Boolean(BooleanColumn),
+}
+
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+pub struct FloatColumn {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+pub struct IntColumn {
+    pub name: String,
+    pub values: Vec<Option<i64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+pub struct StringColumn {
+    pub name: String,
+    pub values: Vec<Option<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, SimpleObject)]
+pub struct BooleanColumn {
+    pub name: String,
+    pub values: Vec<Option<bool>>,
+}
+
+impl ColumnValues {
+    /// Convert JSON values to the given kind. Values that can't be converted are null.
+    pub fn new(name: String, kind: ColumnKind, values: Vec<Value>) -> Self {
+        match kind {
+            ColumnKind::Float => Self::Float(FloatColumn {
+                name,
+                values: values.iter().map(Value::as_f64).collect(),
+            }),
+            ColumnKind::Int => Self::Int(IntColumn {
+                name,
+                values: values.iter().map(Value::as_i64).collect(),
+            }),
+            ColumnKind::Boolean => Self::Boolean(BooleanColumn {
+                name,
+                values: values.iter().map(Value::as_bool).collect(),
+            }),
+            ColumnKind::String => Self::String(StringColumn {
+                name,
+                values: values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Null => None,
+                        Value::String(s) => Some(s),
+                        other => Some(other.to_string()),
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::{Column, ColumnKind, ColumnValues, FloatColumn, IntColumn, TableStructure};
+
+    #[test]
+    fn column_info_from_arrow_schema() {
+        let table: Value = serde_json::from_str(
+            &std::fs::read_to_string("resources/metadata_table.json").unwrap(),
+        )
+        .unwrap();
+        let structure: TableStructure =
+            serde_json::from_value(table["data"]["attributes"]["structure"].clone()).unwrap();
+        let column = |name: &str, data_type: &str, kind| Column {
+            name: name.into(),
+            data_type: data_type.into(),
+            kind,
+            nullable: true,
+        };
+        assert_eq!(
+            structure.column_info().unwrap(),
+            [
+                column("seq_num", "Int64", ColumnKind::Int),
+                column("time", "Float64", ColumnKind::Float),
+                column("stage-x", "Float64", ColumnKind::Float),
+                column("ts_stage-x", "Float64", ColumnKind::Float),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_arrow_schema() {
+        let structure = TableStructure {
+            arrow_schema: "data:application/vnd.apache.arrow.file;base64,AAAA".into(),
+            npartitions: 1,
+            columns: vec![],
+            resizable: false,
+        };
+        assert!(structure.column_info().is_err());
+    }
+
+    #[test]
+    fn typed_column_values() {
+        assert_eq!(
+            ColumnValues::new("x".into(), ColumnKind::Float, vec![json!(1), json!(2.5)]),
+            ColumnValues::Float(FloatColumn {
+                name: "x".into(),
+                values: vec![Some(1.0), Some(2.5)]
+            })
+        );
+        assert_eq!(
+            ColumnValues::new("n".into(), ColumnKind::Int, vec![json!(1), json!(null)]),
+            ColumnValues::Int(IntColumn {
+                name: "n".into(),
+                values: vec![Some(1), None]
+            })
+        );
+    }
+}
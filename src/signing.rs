@@ -0,0 +1,240 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::HeaderValue;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chacha20poly1305::aead::{Aead, Generate, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::handlers::AuthHeader;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of the random nonce prepended to an encrypted credential
+const NONCE_LEN: usize = 24;
+
+/// Signs download links so that they can be followed without an Authorization header. The
+/// caller's credential is encrypted into the link so that it can be forwarded to tiled when the
+/// link is used, and the link is only valid until it expires.
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: Vec<u8>,
+    cipher: XChaCha20Poly1305,
+    lifetime: Duration,
+}
+
+/// Query parameters added to a signed link
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// Time the link expires, in seconds since the epoch
+    pub expires: u64,
+    /// The encrypted credential of the user the link was created for, if any
+    pub token: Option<String>,
+    pub signature: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureError {
+    Expired,
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Expired => f.write_str("Download link has expired"),
+            SignatureError::Invalid => f.write_str("Download link signature is not valid"),
+        }
+    }
+}
+
+impl UrlSigner {
+    pub fn new(key: &str, lifetime: Duration) -> Self {
+        // Use a separate key, derived from the signing key, to encrypt credentials
+        let mut derive =
+            HmacSha256::new_from_slice(key.as_bytes()).expect("Any key length is valid");
+        derive.update(b"glazed credential encryption");
+        let cipher = XChaCha20Poly1305::new_from_slice(&derive.finalize().into_bytes())
+            .expect("HMAC output is a valid key");
+        Self {
+            key: key.as_bytes().to_vec(),
+            cipher,
+            lifetime,
+        }
+    }
+
+    /// Sign a link to `path`, valid for the configured lifetime from now
+    pub fn sign(&self, path: &str, auth: Option<&AuthHeader>) -> Signature {
+        self.sign_at(path, auth, now())
+    }
+
+    fn sign_at(&self, path: &str, auth: Option<&AuthHeader>, now: u64) -> Signature {
+        let expires = now + self.lifetime.as_secs();
+        let token = auth.map(|auth| {
+            let nonce = XNonce::generate();
+            let aad = format!("{path}\n{expires}");
+            let mut sealed = nonce.to_vec();
+            sealed.extend(
+                self.cipher
+                    .encrypt(
+                        &nonce,
+                        Payload {
+                            msg: auth.as_bytes(),
+                            aad: aad.as_bytes(),
+                        },
+                    )
+                    .expect("Credential is not too long to encrypt"),
+            );
+            URL_SAFE_NO_PAD.encode(sealed)
+        });
+        let signature = URL_SAFE_NO_PAD.encode(
+            self.mac(path, expires, token.as_deref())
+                .finalize()
+                .into_bytes(),
+        );
+        Signature {
+            expires,
+            token,
+            signature,
+        }
+    }
+
+    /// Check a link to `path` was signed by us and has not expired. Returns the credential of
+    /// the user the link was created for, if there was one.
+    pub fn verify(
+        &self,
+        path: &str,
+        signature: &Signature,
+    ) -> Result<Option<AuthHeader>, SignatureError> {
+        self.verify_at(path, signature, now())
+    }
+
+    fn verify_at(
+        &self,
+        path: &str,
+        signature: &Signature,
+        now: u64,
+    ) -> Result<Option<AuthHeader>, SignatureError> {
+        let sig = URL_SAFE_NO_PAD
+            .decode(&signature.signature)
+            .map_err(|_| SignatureError::Invalid)?;
+        self.mac(path, signature.expires, signature.token.as_deref())
+            .verify_slice(&sig)
+            .map_err(|_| SignatureError::Invalid)?;
+        if signature.expires < now {
+            return Err(SignatureError::Expired);
+        }
+        let Some(token) = &signature.token else {
+            return Ok(None);
+        };
+        let sealed = URL_SAFE_NO_PAD
+            .decode(token)
+            .map_err(|_| SignatureError::Invalid)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(SignatureError::Invalid);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = XNonce::try_from(nonce).map_err(|_| SignatureError::Invalid)?;
+        let aad = format!("{path}\n{}", signature.expires);
+        let credential = self
+            .cipher
+            .decrypt(
+                &nonce,
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| SignatureError::Invalid)?;
+        HeaderValue::from_bytes(&credential)
+            .map(|value| Some(AuthHeader::from(value)))
+            .map_err(|_| SignatureError::Invalid)
+    }
+
+    fn mac(&self, path: &str, expires: u64, token: Option<&str>) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("Any key length is valid");
+        mac.update(format!("{path}\n{expires}\n{}", token.unwrap_or_default()).as_bytes());
+        mac
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time is after the epoch")
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::HeaderValue;
+
+    use super::{SignatureError, UrlSigner};
+    use crate::handlers::AuthHeader;
+
+    const PATH: &str = "asset/run/primary/det/18";
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new("secret", Duration::from_secs(900))
+    }
+
+    fn auth() -> AuthHeader {
+        AuthHeader::from(HeaderValue::from_static("Bearer abc123"))
+    }
+
+    #[test]
+    fn signed_link_round_trip() {
+        let signer = signer();
+        let signature = signer.sign_at(PATH, Some(&auth()), 1000);
+        assert_eq!(signature.expires, 1900);
+        // The credential is not readable from the link
+        assert!(!signature.token.as_ref().unwrap().contains("abc123"));
+        assert_eq!(signer.verify_at(PATH, &signature, 1500), Ok(Some(auth())));
+    }
+
+    #[test]
+    fn signed_link_without_credential() {
+        let signer = signer();
+        let signature = signer.sign_at(PATH, None, 1000);
+        assert_eq!(signature.token, None);
+        assert_eq!(signer.verify_at(PATH, &signature, 1500), Ok(None));
+    }
+
+    #[test]
+    fn expired_link() {
+        let signer = signer();
+        let signature = signer.sign_at(PATH, Some(&auth()), 1000);
+        assert_eq!(
+            signer.verify_at(PATH, &signature, 2000),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn tampered_links() {
+        let signer = signer();
+        let signature = signer.sign_at(PATH, Some(&auth()), 1000);
+        let verify = |path, signature| signer.verify_at(path, &signature, 1500);
+
+        assert_eq!(
+            verify("asset/run/primary/det/19", signature.clone()),
+            Err(SignatureError::Invalid)
+        );
+        let mut extended = signature.clone();
+        extended.expires += 3600;
+        assert_eq!(verify(PATH, extended), Err(SignatureError::Invalid));
+        let mut anonymous = signature.clone();
+        anonymous.token = None;
+        assert_eq!(verify(PATH, anonymous), Err(SignatureError::Invalid));
+
+        let other = UrlSigner::new("other", Duration::from_secs(900));
+        assert_eq!(
+            other.verify_at(PATH, &signature, 1500),
+            Err(SignatureError::Invalid)
+        );
+    }
+}